@@ -1,128 +1,59 @@
-use bevy::prelude::*;
-use bevy::render::mesh::{Mesh, PrimitiveTopology};
+use bevy_inspector_egui::Inspectable;
+use serde::{Deserialize, Serialize};
 
 pub const BLOCK_SIZE: f32 = 0.1;
 pub const BLOCK_HEIGHT: f32 = 0.01;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Inspectable)]
 pub enum BlockType {
     Flat,
+    /// 22.5° slope up
+    GentleUphill,
+    /// 22.5° slope down
+    GentleDownhill,
+    /// 45° slope up
     Uphill,
+    /// 45° slope down
     Downhill,
+    /// No ground, falling in ends in a pit
+    Gap,
+    /// No ground, with a platform above it that can be jumped up through
+    Platform,
+    /// Flat ground that hurts whatever touches it
+    Spikes,
+    /// Flat ground that launches whatever touches it upwards
+    Bounce,
 }
 
 impl BlockType {
-    pub fn to_sprite(&self) -> SpriteBundle {
-        let sprite = Sprite {
-            custom_size: Some(Vec2::new(BLOCK_SIZE, BLOCK_HEIGHT)),
-            ..Default::default()
-        };
-
-        return match self {
-            BlockType::Flat => SpriteBundle {
-                sprite,
-                transform: Transform::default(),
-                ..Default::default()
-            },
-            BlockType::Uphill => {
-                let angle = 45.0_f32.to_radians();
-
-                SpriteBundle {
-                    sprite,
-                    transform: Transform::default()
-                        .with_rotation(Quat::from_axis_angle(Vec3::Z, angle)),
-                    ..Default::default()
-                }
-            }
-            BlockType::Downhill => {
-                let angle = 315.0_f32.to_radians();
-
-                SpriteBundle {
-                    sprite,
-                    transform: Transform::default()
-                        .with_rotation(Quat::from_axis_angle(Vec3::Z, angle)),
-                    ..Default::default()
-                }
-            }
-        };
+    /// How much higher the right edge of the block is than the left.
+    /// Every block is `BLOCK_SIZE` wide, so slopes are as steep as this is high.
+    pub fn rise(&self) -> f32 {
+        match self {
+            BlockType::GentleUphill => BLOCK_SIZE * 22.5_f32.to_radians().tan(),
+            BlockType::GentleDownhill => -BLOCK_SIZE * 22.5_f32.to_radians().tan(),
+            BlockType::Uphill => BLOCK_SIZE,
+            BlockType::Downhill => -BLOCK_SIZE,
+            _ => 0.0,
+        }
     }
 
-    fn to_mesh(&self) -> Mesh {
-        return match self {
-            BlockType::Flat => {
-                let vertices = [
-                    ([0.5, 0.5, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0]),
-                    ([-0.5, 0.5, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0]),
-                ];
-                let mut positions = Vec::<[f32; 3]>::new();
-                let mut normals = Vec::<[f32; 3]>::new();
-                let mut uvs = Vec::<[f32; 2]>::new();
-                for (position, normal, uv) in &vertices {
-                    positions.push(*position);
-                    normals.push(*normal);
-                    uvs.push(*uv);
-                }
-
-                let mut mesh = Mesh::new(PrimitiveTopology::LineList);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-                mesh
-            }
-            BlockType::Uphill => {
-                let vertices = [
-                    ([-0.5, -0.5, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0]),
-                    ([0.5, 0.5, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0]),
-                ];
-
-                let mut positions = Vec::<[f32; 3]>::new();
-                let mut normals = Vec::<[f32; 3]>::new();
-                let mut uvs = Vec::<[f32; 2]>::new();
-                for (position, normal, uv) in &vertices {
-                    positions.push(*position);
-                    normals.push(*normal);
-                    uvs.push(*uv);
-                }
-
-                let mut mesh = Mesh::new(PrimitiveTopology::LineList);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-                mesh
-            }
-            BlockType::Downhill => {
-                let vertices = [
-                    ([-0.5, 1.5, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0]),
-                    ([0.5, 0.5, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0]),
-                ];
-
-                let mut positions = Vec::<[f32; 3]>::new();
-                let mut normals = Vec::<[f32; 3]>::new();
-                let mut uvs = Vec::<[f32; 2]>::new();
-                for (position, normal, uv) in &vertices {
-                    positions.push(*position);
-                    normals.push(*normal);
-                    uvs.push(*uv);
-                }
-
-                let mut mesh = Mesh::new(PrimitiveTopology::LineList);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-                mesh
-            }
-        };
+    /// Whether the block has ground to stand on
+    pub fn is_solid(&self) -> bool {
+        !matches!(self, BlockType::Gap | BlockType::Platform)
     }
-}
 
-impl From<BlockType> for SpriteBundle {
-    fn from(block: BlockType) -> Self {
-        block.to_sprite()
+    /// Whether the block is plain ground something can safely stand on
+    pub fn is_walkable(&self) -> bool {
+        self.is_solid() && !matches!(self, BlockType::Spikes | BlockType::Bounce)
     }
-}
 
-impl From<BlockType> for Mesh {
-    fn from(block: BlockType) -> Self {
-        block.to_mesh()
+    /// `1` for slopes going up, `-1` for slopes going down and `0` for everything else
+    pub fn slope(&self) -> i32 {
+        match self {
+            BlockType::GentleUphill | BlockType::Uphill => 1,
+            BlockType::GentleDownhill | BlockType::Downhill => -1,
+            _ => 0,
+        }
     }
 }
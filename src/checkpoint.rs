@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use heron::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block_type::{BlockType, BLOCK_SIZE},
+    config::GameConfig,
+    level::RestartLevelEvent,
+    level_file::CurrentLevel,
+    terrain::{ChunkMember, ChunkSpawnedEvent},
+    utils::Layers,
+};
+
+const POST_HEIGHT: f32 = 0.3;
+const POST_WIDTH: f32 = 0.03;
+/// How far past its spot a checkpoint moves to find ground to stand on
+const SEARCH_BLOCKS: usize = 5;
+/// Height above the ground the player respawns at
+const RESPAWN_HEIGHT: f32 = 0.2;
+
+const UNREACHED_COLOR: Color = Color::GRAY;
+const REACHED_COLOR: Color = Color::ORANGE;
+
+pub struct CheckpointPlugin;
+
+impl Plugin for CheckpointPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CheckpointReachedEvent>()
+            .init_resource::<Checkpoint>()
+            .add_system(spawn_checkpoints)
+            .add_system(reach_checkpoint)
+            .add_system(reset_checkpoint);
+    }
+}
+
+/// Post along the level the player comes back to after falling or dying
+#[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointPost {
+    pub block: u32,
+    /// Where the player respawns once it's been reached
+    pub respawn: Vec3,
+}
+
+/// Last checkpoint the player reached this level, `None` until they reach one
+#[derive(Debug, Default)]
+pub struct Checkpoint(pub Option<CheckpointPost>);
+
+/// Sent when the player touches a checkpoint post
+pub struct CheckpointReachedEvent(pub Entity);
+
+/// Blocks of `blocks` that have a checkpoint, one every `spacing` blocks of the level.
+/// Each one moves forwards onto walkable ground, and is left out if there is none close by.
+pub fn checkpoint_blocks(blocks: &[BlockType], first_block: u32, spacing: u32) -> Vec<u32> {
+    let spacing = spacing.max(1);
+    let end = first_block + blocks.len() as u32;
+    let mut checkpoints = Vec::new();
+
+    // The spawn point already acts as the first checkpoint, so block 0 never gets one
+    let mut spot = (first_block.max(1) + spacing - 1) / spacing * spacing;
+
+    while spot < end {
+        let start = (spot - first_block) as usize;
+        let search = start..(start + SEARCH_BLOCKS).min(blocks.len());
+
+        if let Some(i) = search.into_iter().find(|i| blocks[*i].is_walkable()) {
+            checkpoints.push(first_block + i as u32);
+        }
+
+        spot += spacing;
+    }
+
+    checkpoints
+}
+
+fn spawn_checkpoints(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    level: Res<CurrentLevel>,
+    checkpoint: Res<Checkpoint>,
+    mut events: EventReader<ChunkSpawnedEvent>,
+) {
+    for ev in events.iter() {
+        let blocks = match &level.0 {
+            Some(file) => file.checkpoints.clone(),
+            None => checkpoint_blocks(
+                &ev.blocks,
+                ev.first_block,
+                config.terrain.checkpoint_spacing,
+            ),
+        };
+
+        for block in blocks {
+            let ground = match block.checked_sub(ev.first_block) {
+                Some(i) if (i as usize) < ev.surface.len() => ev.surface[i as usize],
+                _ => continue,
+            };
+
+            // Chunks come back after unloading, the posts in them remember being reached
+            let reached = matches!(checkpoint.0, Some(reached) if reached.block >= block);
+            spawn_post(&mut commands, ev.chunk, block, ground, reached);
+        }
+    }
+}
+
+fn spawn_post(commands: &mut Commands, chunk: u32, block: u32, ground: Vec2, reached: bool) {
+    let post = shapes::Rectangle {
+        extents: Vec2::new(POST_WIDTH, POST_HEIGHT),
+        origin: shapes::RectangleOrigin::Center,
+    };
+
+    commands
+        .spawn_bundle(GeometryBuilder::build_as(
+            &post,
+            DrawMode::Fill(FillMode::color(post_color(reached))),
+            Transform::from_xyz(ground.x, ground.y + POST_HEIGHT / 2.0, 850.0),
+        ))
+        .insert(Name::new("Checkpoint"))
+        .insert(CheckpointPost {
+            block,
+            respawn: Vec3::new(ground.x, ground.y + RESPAWN_HEIGHT, 999.0),
+        })
+        .insert(ChunkMember(chunk))
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Cuboid {
+            half_extends: Vec3::new(BLOCK_SIZE / 2.0, POST_HEIGHT / 2.0, 0.0),
+            border_radius: None,
+        })
+        .insert(
+            CollisionLayers::none()
+                .with_group(Layers::Trigger)
+                .with_mask(Layers::Player),
+        );
+}
+
+/// Remembers the checkpoint the player touched, unless they've already been further
+fn reach_checkpoint(
+    mut checkpoint: ResMut<Checkpoint>,
+    mut events: EventReader<CheckpointReachedEvent>,
+    mut post_query: Query<(&CheckpointPost, &mut DrawMode)>,
+) {
+    for ev in events.iter() {
+        let (post, mut draw_mode) = match post_query.get_mut(ev.0) {
+            Ok(post) => post,
+            Err(_) => continue,
+        };
+
+        if matches!(checkpoint.0, Some(reached) if reached.block >= post.block) {
+            continue;
+        }
+
+        info!("reached the checkpoint at block {}", post.block);
+        checkpoint.0 = Some(*post);
+        *draw_mode = DrawMode::Fill(FillMode::color(post_color(true)));
+    }
+}
+
+fn post_color(reached: bool) -> Color {
+    if reached {
+        REACHED_COLOR
+    } else {
+        UNREACHED_COLOR
+    }
+}
+
+fn reset_checkpoint(
+    mut checkpoint: ResMut<Checkpoint>,
+    mut events: EventReader<RestartLevelEvent>,
+) {
+    if events.iter().last().is_some() {
+        checkpoint.0 = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoints_move_onto_walkable_ground() {
+        let mut blocks = vec![BlockType::Flat; 50];
+        blocks[0] = BlockType::Gap;
+        blocks[1] = BlockType::Spikes;
+        blocks[20] = BlockType::Gap;
+
+        assert_eq!(checkpoint_blocks(&blocks, 100, 20), vec![102, 121, 140]);
+        assert_eq!(checkpoint_blocks(&blocks, 0, 20), vec![21, 40]);
+        assert!(checkpoint_blocks(&vec![BlockType::Gap; 50], 100, 20).is_empty());
+    }
+}
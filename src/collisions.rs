@@ -1,29 +1,56 @@
 use bevy::prelude::*;
 use heron::prelude::*;
 
-use crate::{health::DamageEvent, hud::UpdatePepperCountEvent, player::Player, utils::Layers};
+use crate::{
+    checkpoint::{CheckpointPost, CheckpointReachedEvent},
+    config::GameConfig,
+    enemy::ContactDamage,
+    goal::{Goal, LevelCompleteEvent},
+    health::{DamageEvent, DamageKind},
+    hud::UpdatePepperCountEvent,
+    pepper::{Pepper, PepperCollectedEvent},
+    player::Player,
+    state::AppState,
+    terrain::{BouncePad, ChunkMember, FellInPitEvent, Pit, Spikes},
+    utils::Layers,
+};
 
 pub struct CollisionsPlugin;
 
 impl Plugin for CollisionsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(collisions);
+        app.add_system_set(SystemSet::on_update(AppState::Playing).with_system(collisions));
     }
 }
 
 fn collisions(
     mut commands: Commands,
     mut player_query: Query<&mut Player>,
+    contact_query: Query<&ContactDamage>,
+    pepper_query: Query<(&Pepper, &ChunkMember)>,
+    trigger_query: Query<(
+        Option<&Pit>,
+        Option<&Spikes>,
+        Option<&BouncePad>,
+        Option<&Goal>,
+        Option<&CheckpointPost>,
+    )>,
+    mut velocity_query: Query<&mut Velocity>,
     mut events: EventReader<CollisionEvent>,
     mut pepper_event: EventWriter<UpdatePepperCountEvent>,
+    mut collected_event: EventWriter<PepperCollectedEvent>,
     mut damage_event: EventWriter<DamageEvent>,
+    mut fell_event: EventWriter<FellInPitEvent>,
+    mut complete_event: EventWriter<LevelCompleteEvent>,
+    mut checkpoint_event: EventWriter<CheckpointReachedEvent>,
+    config: Res<GameConfig>,
 ) {
     for event in events.iter() {
         let data = entities_from_event(event);
 
         let entities: &[(Entity, Layers); 2] = match data.as_slice().try_into() {
             Ok(data) => data,
-            Err(_) => break,
+            Err(_) => continue,
         };
 
         let player = entities.iter().find(|item| item.1 == Layers::Player);
@@ -31,6 +58,8 @@ fn collisions(
         let fireball = entities.iter().find(|item| item.1 == Layers::Fireball);
         let level = entities.iter().find(|item| item.1 == Layers::Level);
         let enemy = entities.iter().find(|item| item.1 == Layers::Enemy);
+        let enemy_fireball = entities.iter().find(|item| item.1 == Layers::EnemyFireball);
+        let trigger = entities.iter().find(|item| item.1 == Layers::Trigger);
 
         // Interactions with player
         match player {
@@ -41,32 +70,104 @@ fn collisions(
                 };
 
                 let mut player = player_query.single_mut();
-                player.jumped = !contact;
 
                 match pepper {
                     Some(entity) => {
                         player.peppers += 1;
                         pepper_event.send(UpdatePepperCountEvent(player.peppers));
 
+                        if let Ok((pepper, member)) = pepper_query.get(entity.0) {
+                            collected_event.send(PepperCollectedEvent {
+                                chunk: member.0,
+                                block: pepper.block,
+                            });
+                        }
+
                         commands.entity(entity.0).despawn_recursive();
                     }
                     None => {}
                 }
 
                 match enemy {
-                    Some(_) => {
-                        for _ in 0..100 {
-                            damage_event.send(DamageEvent(*player_entity));
+                    Some((entity, _)) if contact => {
+                        if let Ok(damage) = contact_query.get(*entity) {
+                            damage_event.send(DamageEvent {
+                                target: *player_entity,
+                                amount: damage.0,
+                                source: Some(*entity),
+                                kind: DamageKind::Contact,
+                            });
                         }
                     }
+                    _ => {}
+                }
+
+                match enemy_fireball {
+                    Some((entity, _)) => {
+                        if contact {
+                            damage_event.send(DamageEvent {
+                                target: *player_entity,
+                                amount: config.enemy.fireball_damage,
+                                source: Some(*entity),
+                                kind: DamageKind::Fireball,
+                            });
+                        }
+
+                        commands.entity(*entity).despawn_recursive();
+                    }
                     None => {}
                 }
+
+                match trigger {
+                    Some((entity, _)) if contact => {
+                        if let Ok((_, spikes, _, goal, checkpoint)) = trigger_query.get(*entity) {
+                            if spikes.is_some() {
+                                damage_event.send(DamageEvent {
+                                    target: *player_entity,
+                                    amount: config.terrain.spike_damage,
+                                    source: Some(*entity),
+                                    kind: DamageKind::Hazard,
+                                });
+                            }
+
+                            if goal.is_some() {
+                                complete_event.send(LevelCompleteEvent);
+                            }
+
+                            if checkpoint.is_some() {
+                                checkpoint_event.send(CheckpointReachedEvent(*entity));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
             }
             None => {}
         };
 
+        // Pits and bounce pads work the same on the player and enemies
+        match (trigger, player.or(enemy), event) {
+            (Some((trigger, _)), Some((body, _)), CollisionEvent::Started(_, _)) => {
+                let (pit, _, bounce, ..) = match trigger_query.get(*trigger) {
+                    Ok(trigger) => trigger,
+                    Err(_) => continue,
+                };
+
+                if pit.is_some() {
+                    fell_event.send(FellInPitEvent(*body));
+                }
+
+                if bounce.is_some() {
+                    if let Ok(mut velocity) = velocity_query.get_mut(*body) {
+                        velocity.linear.y = config.terrain.bounce_speed;
+                    }
+                }
+            }
+            _ => {}
+        }
+
         match level {
-            Some(_) => match fireball {
+            Some(_) => match fireball.or(enemy_fireball) {
                 Some((entity, _)) => commands.entity(*entity).despawn_recursive(),
                 None => {}
             },
@@ -77,7 +178,15 @@ fn collisions(
         match enemy {
             Some((entity, _)) => match fireball {
                 Some((fb, _)) => {
-                    damage_event.send(DamageEvent(*entity));
+                    if let CollisionEvent::Started(_, _) = event {
+                        damage_event.send(DamageEvent {
+                            target: *entity,
+                            amount: config.fireball.damage,
+                            source: Some(*fb),
+                            kind: DamageKind::Fireball,
+                        });
+                    }
+
                     commands.entity(*fb).despawn_recursive();
                 }
                 None => {}
@@ -95,9 +204,15 @@ fn entities_from_event(ev: &CollisionEvent) -> Vec<(Entity, Layers)> {
     let queries = [
         (has_group(layers, Layers::Level), Layers::Level),
         (has_group(layers, Layers::Fireball), Layers::Fireball),
+        (
+            has_group(layers, Layers::EnemyFireball),
+            Layers::EnemyFireball,
+        ),
         (has_group(layers, Layers::Enemy), Layers::Enemy),
         (has_group(layers, Layers::Pepper), Layers::Pepper),
         (has_group(layers, Layers::Player), Layers::Player),
+        (has_group(layers, Layers::Trigger), Layers::Trigger),
+        (has_group(layers, Layers::Platform), Layers::Platform),
     ];
 
     for (query, layer) in queries.iter() {
@@ -0,0 +1,201 @@
+use std::marker::PhantomData;
+
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use heron::Gravity;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::controller::CharacterController;
+
+pub struct ConfigPlugin;
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        let config = GameConfig::default();
+
+        app.insert_resource(Gravity::from(config.physics.gravity.extend(0.0)))
+            .add_system(apply_gravity);
+
+        add_ron_asset::<GameConfig>(app);
+    }
+}
+
+/// Data file that is embedded in the binary as its `Default` and kept in sync
+/// with the file in `assets` through a resource of the same type.
+pub trait RonAsset: TypeUuid + DeserializeOwned + Default + Clone + Send + Sync + 'static {
+    /// Path of the file inside `assets`
+    const PATH: &'static str;
+    /// Extensions the loader handles, must not be shared with another asset
+    const EXTENSIONS: &'static [&'static str];
+}
+
+/// Inserts the embedded default as a resource and hot-reloads it from `T::PATH`
+pub fn add_ron_asset<T: RonAsset>(app: &mut App) {
+    app.init_resource::<T>()
+        .add_asset::<T>()
+        .init_asset_loader::<RonAssetLoader<T>>()
+        .add_startup_system(load_ron_asset::<T>)
+        .add_system(reload_ron_asset::<T>);
+}
+
+/// Gameplay tuning values, loaded from `assets/game.config.ron`.
+///
+/// The file is also embedded in the binary so the game can start before the
+/// asset has finished loading.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "90ffb2f9-bcba-4203-b9a7-5026ac56ad5f"]
+pub struct GameConfig {
+    pub player: PlayerConfig,
+    pub enemy: EnemyConfig,
+    pub fireball: FireballConfig,
+    pub physics: PhysicsConfig,
+    pub terrain: TerrainConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlayerConfig {
+    pub controller: CharacterController,
+    pub health: f32,
+    /// Seconds the player can't be hurt after being hit
+    pub invulnerability: f32,
+    /// Velocity the player is pushed away from whatever hit them with
+    pub knockback: Vec2,
+    /// Times the player can die before the game is over
+    pub lives: u32,
+    /// Health lost by falling into a pit or out of the level
+    pub fall_damage: f32,
+    /// Seconds the player can't be hurt after respawning
+    pub respawn_invulnerability: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EnemyConfig {
+    /// Shared by every kind, with the speed and jump set per kind
+    pub controller: CharacterController,
+    pub patrol_range: f32,
+    pub idle_time: f32,
+    pub detection_radius: f32,
+    pub flee_below: f32,
+    pub fire_range: f32,
+    pub fire_interval: f32,
+    pub fireball_damage: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FireballConfig {
+    /// Multiplied by the distance to the target to get the launch speed
+    pub impulse: f32,
+    /// Health the player's fireballs take from enemies
+    pub damage: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PhysicsConfig {
+    pub gravity: Vec2,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TerrainConfig {
+    /// Thickness of the coloured strip along the top of the ground
+    pub surface_depth: f32,
+    /// How far the ground is drawn below the surface
+    pub ground_depth: f32,
+    /// Health spikes take from the player
+    pub spike_damage: f32,
+    /// Upwards speed bounce pads launch things at
+    pub bounce_speed: f32,
+    /// Blocks between checkpoints on generated levels
+    pub checkpoint_spacing: u32,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        ron::de::from_str(include_str!("../assets/game.config.ron"))
+            .expect("embedded game config is invalid")
+    }
+}
+
+impl RonAsset for GameConfig {
+    const PATH: &'static str = "game.config.ron";
+    const EXTENSIONS: &'static [&'static str] = &["config.ron"];
+}
+
+/// Keeps the asset loaded so it can be hot-reloaded
+struct RonAssetHandle<T: RonAsset>(Handle<T>);
+
+struct RonAssetLoader<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for RonAssetLoader<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: RonAsset> AssetLoader for RonAssetLoader<T> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let asset = ron::de::from_bytes::<T>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        T::EXTENSIONS
+    }
+}
+
+fn load_ron_asset<T: RonAsset>(mut commands: Commands, server: Res<AssetServer>) {
+    commands.insert_resource(RonAssetHandle::<T>(server.load(T::PATH)));
+}
+
+/// Copies the loaded asset into its resource whenever the file changes
+fn reload_ron_asset<T: RonAsset>(
+    mut events: EventReader<AssetEvent<T>>,
+    handle: Res<RonAssetHandle<T>>,
+    assets: Res<Assets<T>>,
+    mut resource: ResMut<T>,
+) {
+    for ev in events.iter() {
+        match ev {
+            AssetEvent::Created { handle: changed } | AssetEvent::Modified { handle: changed } => {
+                if *changed != handle.0 {
+                    continue;
+                }
+
+                if let Some(loaded) = assets.get(changed) {
+                    info!("Loaded {}", T::PATH);
+
+                    *resource = loaded.clone();
+                }
+            }
+            AssetEvent::Removed { .. } => {}
+        }
+    }
+}
+
+fn apply_gravity(config: Res<GameConfig>, mut gravity: ResMut<Gravity>) {
+    if config.is_changed() {
+        *gravity = Gravity::from(config.physics.gravity.extend(0.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_config_parses() {
+        let config = GameConfig::default();
+
+        assert!(config.player.health > 0.0);
+        assert!(config.physics.gravity.y < 0.0);
+    }
+}
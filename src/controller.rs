@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+use heron::Velocity;
+use serde::Deserialize;
+
+use crate::{
+    ground::{GroundCheck, Grounded},
+    input::InputFrame,
+    state::AppState,
+};
+
+pub struct ControllerPlugin;
+
+impl Plugin for ControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::Playing)
+                .with_system(move_characters.label(ControllerStep).after(GroundCheck)),
+        );
+    }
+}
+
+/// Systems that write `ControllerInput` should run before this
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct ControllerStep;
+
+/// How something walks and jumps. Speeds are in world units per second.
+#[derive(Component, Debug, Clone, Deserialize)]
+pub struct CharacterController {
+    pub max_speed: f32,
+    /// Speed gained per second on the ground while moving
+    pub acceleration: f32,
+    /// Speed lost per second on the ground while not moving or turning around
+    pub deceleration: f32,
+    pub air_acceleration: f32,
+    pub air_deceleration: f32,
+    pub jump_speed: f32,
+    /// Upwards speed is multiplied by this when jump is let go mid-jump
+    pub jump_cut: f32,
+    pub max_fall_speed: f32,
+    /// Seconds after leaving the ground a jump is still allowed
+    pub coyote_time: f32,
+    /// Seconds a jump press is remembered before landing
+    pub jump_buffer: f32,
+    /// Steepest slope in degrees that is walked along instead of slid down
+    pub max_slope: f32,
+}
+
+/// What a character wants to do this tick, written by the player's input or an enemy's brain
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct ControllerInput {
+    /// From `-1.0` for full speed left to `1.0` for full speed right
+    pub movement: f32,
+    /// Held down, releasing it early cuts the jump short
+    pub jump: bool,
+}
+
+/// Jump bookkeeping kept between ticks
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct ControllerState {
+    /// Time left on the last jump press
+    pub buffered_jump: f32,
+    /// Rising from a jump that hasn't been cut yet
+    pub jumping: bool,
+}
+
+#[derive(Bundle)]
+pub struct ControllerBundle {
+    pub controller: CharacterController,
+    pub input: ControllerInput,
+    pub state: ControllerState,
+}
+
+impl ControllerBundle {
+    pub fn new(controller: CharacterController) -> Self {
+        Self {
+            controller,
+            input: ControllerInput::default(),
+            state: ControllerState::default(),
+        }
+    }
+}
+
+/// Moves `current` towards `target` by at most `step`
+fn approach(current: f32, target: f32, step: f32) -> f32 {
+    if current < target {
+        (current + step).min(target)
+    } else {
+        (current - step).max(target)
+    }
+}
+
+fn move_characters(
+    mut query: Query<(
+        &CharacterController,
+        &ControllerInput,
+        &mut ControllerState,
+        Option<&mut Grounded>,
+        &mut Velocity,
+    )>,
+    frame: Res<InputFrame>,
+) {
+    let delta = frame.delta;
+
+    for (controller, input, mut state, mut grounded, mut velocity) in query.iter_mut() {
+        let target = input.movement.clamp(-1.0, 1.0) * controller.max_speed;
+
+        let walkable = grounded.as_ref().and_then(|grounded| {
+            let slope = grounded.normal.angle_between(Vec3::Y).to_degrees();
+            (grounded.on_ground && slope <= controller.max_slope).then(|| grounded.normal)
+        });
+
+        match walkable {
+            Some(normal) => {
+                // Move along the surface so slopes don't launch or bounce the character
+                let normal = normal.truncate().normalize();
+                let tangent = Vec2::new(normal.y, -normal.x);
+                let linear = velocity.linear.truncate();
+
+                let speed = linear.dot(tangent);
+                let rate = if target != 0.0 && target.signum() == speed.signum() {
+                    controller.acceleration
+                } else {
+                    controller.deceleration
+                };
+                let speed = approach(speed, target, rate * delta);
+
+                // Keep anything pushing away from the ground, like knockback
+                let away = linear.dot(normal).max(0.0);
+                let linear = tangent * speed + normal * away;
+
+                velocity.linear.x = linear.x;
+                velocity.linear.y = linear.y;
+            }
+            None => {
+                let rate = if target != 0.0 {
+                    controller.air_acceleration
+                } else {
+                    controller.air_deceleration
+                };
+
+                velocity.linear.x = approach(velocity.linear.x, target, rate * delta);
+            }
+        }
+
+        if input.jump {
+            state.buffered_jump = controller.jump_buffer;
+        } else {
+            state.buffered_jump -= delta;
+        }
+
+        if let Some(grounded) = grounded.as_mut() {
+            let can_jump = !state.jumping && grounded.can_jump(controller.coyote_time);
+
+            if state.buffered_jump > 0.0 && can_jump {
+                velocity.linear.y = controller.jump_speed;
+                state.buffered_jump = 0.0;
+                state.jumping = true;
+                grounded.leave();
+            }
+        }
+
+        if state.jumping {
+            if velocity.linear.y <= 0.0 {
+                state.jumping = false;
+            } else if !input.jump {
+                velocity.linear.y *= controller.jump_cut;
+                state.jumping = false;
+            }
+        }
+
+        velocity.linear.y = velocity.linear.y.max(-controller.max_fall_speed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approach_stops_at_target() {
+        assert_eq!(approach(0.0, 1.0, 0.4), 0.4);
+        assert_eq!(approach(0.8, 1.0, 0.4), 1.0);
+        assert_eq!(approach(0.0, -1.0, 2.0), -1.0);
+    }
+}
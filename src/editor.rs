@@ -0,0 +1,357 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{
+    bevy_egui::EguiContext, plugin::InspectorWindows, Inspectable, InspectorPlugin,
+};
+
+use crate::{
+    block_type::{BlockType, BLOCK_SIZE},
+    camera::MainCamera,
+    checkpoint::checkpoint_blocks,
+    config::GameConfig,
+    enemy::{random_enemies, Enemy, ENEMY_SALT},
+    enemy_kind::EnemyKinds,
+    fireball::get_world_coords,
+    level::{LevelNumber, RestartLevelEvent},
+    level_file::{CurrentLevel, EnemySpawn, LevelFile},
+    pepper::{random_blocks, Pepper, PEPPER_SALT},
+    state::AppState,
+    terrain::{generate_blocks, LevelLength, TerrainSeed, CHUNK_LEN, MAP_LEN},
+    traversal::{self, Reach},
+    utils::arg_value,
+};
+
+/// Switches between playing and editing the level
+const TOGGLE_KEY: KeyCode = KeyCode::F2;
+/// World units per second the camera pans with the arrow keys
+const PAN_SPEED: f32 = 3.0;
+/// How close the cursor has to be to a pepper or enemy to pick it up
+const PICK_RADIUS: f32 = BLOCK_SIZE;
+/// Where a level is saved when the game wasn't started with `--level`
+const DEFAULT_PATH: &str = "levels/edited.level.ron";
+
+/// Paints blocks and moves peppers and enemies around, then saves the result as a `LevelFile`.
+///
+/// Toggled with F2 while playing. Generated terrain is turned into a level file
+/// when editing starts, so it can be used as a starting point.
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(InspectorPlugin::<Editor>::new())
+            .init_resource::<Dragging>()
+            .add_startup_system(hide_editor)
+            .add_system(toggle_editor)
+            .add_system_set(
+                SystemSet::on_enter(AppState::Editing)
+                    .with_system(start_editing)
+                    .with_system(show_editor),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Editing).with_system(hide_editor))
+            .add_system_set(
+                SystemSet::on_update(AppState::Editing)
+                    .with_system(pan_camera)
+                    .with_system(select_brush)
+                    .with_system(drag_things)
+                    .with_system(paint_blocks.after(drag_things))
+                    .with_system(save_level),
+            );
+    }
+}
+
+/// Editor settings, shown in an inspector window while editing
+#[derive(Inspectable)]
+pub struct Editor {
+    /// Block painted with the left mouse button, number keys pick one too
+    pub brush: BlockType,
+    /// Where Ctrl+S saves the level, relative to the assets folder
+    pub path: String,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self {
+            brush: BlockType::Flat,
+            path: arg_value("--level").unwrap_or_else(|| DEFAULT_PATH.to_string()),
+        }
+    }
+}
+
+/// Pepper or enemy being moved with the mouse
+#[derive(Default)]
+struct Dragging(Option<Dragged>);
+
+#[derive(Clone, Copy)]
+struct Dragged {
+    entity: Entity,
+    /// Block it was picked up from, to find it in the level file
+    from: u32,
+}
+
+/// Brushes in the order of the number keys
+const BRUSHES: [(KeyCode, BlockType); 9] = [
+    (KeyCode::Key1, BlockType::Flat),
+    (KeyCode::Key2, BlockType::GentleUphill),
+    (KeyCode::Key3, BlockType::GentleDownhill),
+    (KeyCode::Key4, BlockType::Uphill),
+    (KeyCode::Key5, BlockType::Downhill),
+    (KeyCode::Key6, BlockType::Gap),
+    (KeyCode::Key7, BlockType::Platform),
+    (KeyCode::Key8, BlockType::Spikes),
+    (KeyCode::Key9, BlockType::Bounce),
+];
+
+fn toggle_editor(mut keys: ResMut<Input<KeyCode>>, mut state: ResMut<State<AppState>>) {
+    if !keys.just_pressed(TOGGLE_KEY) {
+        return;
+    }
+
+    let result = match state.current() {
+        AppState::Playing => state.push(AppState::Editing),
+        AppState::Editing => state.pop(),
+        _ => return,
+    };
+
+    if let Err(err) = result {
+        warn!("could not toggle the editor: {:?}", err);
+    }
+
+    // Stops the key from being read again when the stage re-runs for the transition
+    keys.reset(TOGGLE_KEY);
+}
+
+fn show_editor(mut windows: ResMut<InspectorWindows>) {
+    windows.window_data_mut::<Editor>().visible = true;
+}
+
+fn hide_editor(mut windows: ResMut<InspectorWindows>) {
+    windows.window_data_mut::<Editor>().visible = false;
+}
+
+/// Turns generated terrain into a level file, with the peppers and enemies the
+/// seed places in every chunk, not only the ones loaded at the moment
+fn start_editing(
+    mut level: ResMut<CurrentLevel>,
+    seed: Res<TerrainSeed>,
+    length: Res<LevelLength>,
+    number: Res<LevelNumber>,
+    config: Res<GameConfig>,
+    kinds: Res<EnemyKinds>,
+) {
+    if level.0.is_some() {
+        return;
+    }
+
+    let length = length.blocks().unwrap_or_else(|| {
+        warn!(
+            "endless levels can't be saved, editing the first {} blocks",
+            MAP_LEN
+        );
+        MAP_LEN
+    });
+
+    let blocks: Vec<BlockType> = generate_blocks(&seed, length, &Reach::new(&config))
+        .into_iter()
+        .map(|(block, _)| block)
+        .collect();
+    let checkpoints = checkpoint_blocks(&blocks, 0, config.terrain.checkpoint_spacing);
+
+    // Placed chunk by chunk from the same rngs as when the chunks stream in
+    let mut peppers = Vec::new();
+    let mut enemies = Vec::new();
+
+    for (chunk, chunk_blocks) in blocks.chunks(CHUNK_LEN as usize).enumerate() {
+        let chunk = chunk as u32;
+        let first = chunk * CHUNK_LEN;
+
+        let mut rng = seed.rng_at(PEPPER_SALT, chunk);
+        peppers.extend(random_blocks(&mut rng, chunk_blocks, first));
+
+        let mut rng = seed.rng_at(ENEMY_SALT, chunk);
+        let spawns = random_enemies(&mut rng, &kinds, *number, chunk_blocks, first);
+        enemies.extend(spawns.into_iter().map(|(block, kind)| EnemySpawn {
+            block,
+            kind: kind.name.clone(),
+        }));
+    }
+
+    level.0 = Some(LevelFile {
+        name: format!("Level {}", seed.0),
+        spawn: 0,
+        goal: length - 1,
+        blocks,
+        peppers,
+        enemies,
+        checkpoints,
+    });
+}
+
+fn pan_camera(
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+) {
+    let mut direction = Vec2::ZERO;
+
+    for (key, step) in [
+        (KeyCode::Left, -Vec2::X),
+        (KeyCode::Right, Vec2::X),
+        (KeyCode::Up, Vec2::Y),
+        (KeyCode::Down, -Vec2::Y),
+    ] {
+        if keys.pressed(key) {
+            direction += step;
+        }
+    }
+
+    let mut transform = camera_query.single_mut();
+    transform.translation += (direction * PAN_SPEED * time.delta_seconds()).extend(0.0);
+}
+
+fn select_brush(keys: Res<Input<KeyCode>>, mut editor: ResMut<Editor>) {
+    for (key, brush) in BRUSHES {
+        if keys.just_pressed(key) {
+            editor.brush = brush;
+        }
+    }
+}
+
+/// Picks up the pepper or enemy under the cursor and drops it on the block it's released over
+fn drag_things(
+    mut dragging: ResMut<Dragging>,
+    mouse: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut egui: ResMut<EguiContext>,
+    mut level: ResMut<CurrentLevel>,
+    mut things: Query<
+        (Entity, &mut Transform, Option<&Pepper>, &Name),
+        Or<(With<Pepper>, With<Enemy>)>,
+    >,
+    mut restart: EventWriter<RestartLevelEvent>,
+) {
+    let cursor = match get_world_coords(&windows, &q_camera) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    let file = match level.0.as_mut() {
+        Some(file) => file,
+        None => return,
+    };
+
+    if mouse.just_pressed(MouseButton::Left) && !egui.ctx_mut().wants_pointer_input() {
+        dragging.0 = things
+            .iter()
+            .find(|(_, transform, ..)| {
+                transform.translation.truncate().distance(cursor) < PICK_RADIUS
+            })
+            .map(|(entity, transform, ..)| Dragged {
+                entity,
+                from: file.block_at(transform.translation.x),
+            });
+    }
+
+    let dragged = match dragging.0 {
+        Some(dragged) => dragged,
+        None => return,
+    };
+
+    let (_, mut transform, pepper, name) = match things.get_mut(dragged.entity) {
+        Ok(thing) => thing,
+        Err(_) => {
+            dragging.0 = None;
+            return;
+        }
+    };
+
+    transform.translation = cursor.extend(transform.translation.z);
+
+    if !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+
+    dragging.0 = None;
+    let to = file.block_at(cursor.x);
+
+    // Things may have wandered off their block, so move whichever is closest to where it was
+    let distance = |block: u32| (block as i64 - dragged.from as i64).abs();
+    let moved = match pepper {
+        Some(_) => file
+            .peppers
+            .iter_mut()
+            .min_by_key(|block| distance(**block)),
+        None => file
+            .enemies
+            .iter_mut()
+            .filter(|spawn| spawn.kind == name.as_str())
+            .map(|spawn| &mut spawn.block)
+            .min_by_key(|block| distance(**block)),
+    };
+
+    if let Some(block) = moved {
+        *block = to;
+        restart.send(RestartLevelEvent);
+    }
+}
+
+/// Paints the brush onto the block under the cursor while the mouse is held
+fn paint_blocks(
+    editor: Res<Editor>,
+    dragging: Res<Dragging>,
+    mouse: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut egui: ResMut<EguiContext>,
+    mut level: ResMut<CurrentLevel>,
+    mut restart: EventWriter<RestartLevelEvent>,
+) {
+    if !mouse.pressed(MouseButton::Left)
+        || dragging.0.is_some()
+        || egui.ctx_mut().wants_pointer_input()
+    {
+        return;
+    }
+
+    let (file, cursor) = match (level.0.as_mut(), get_world_coords(&windows, &q_camera)) {
+        (Some(file), Some(cursor)) => (file, cursor),
+        _ => return,
+    };
+
+    let block = file.block_at(cursor.x) as usize;
+    match file.blocks.get_mut(block) {
+        Some(painted) if *painted != editor.brush => *painted = editor.brush,
+        _ => return,
+    }
+
+    // Everything after the block may have moved up or down, so the level is rebuilt
+    restart.send(RestartLevelEvent);
+}
+
+fn save_level(
+    keys: Res<Input<KeyCode>>,
+    editor: Res<Editor>,
+    level: Res<CurrentLevel>,
+    config: Res<GameConfig>,
+) {
+    let ctrl = keys.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    if !ctrl || !keys.just_pressed(KeyCode::S) {
+        return;
+    }
+
+    let file = match &level.0 {
+        Some(file) => file,
+        None => return,
+    };
+
+    for problem in traversal::validate(&file.block_rises(), &Reach::new(&config)) {
+        warn!("{}: {}", file.name, problem);
+    }
+
+    let path = Path::new("assets").join(&editor.path);
+    match file.save(&path) {
+        Ok(()) => info!("saved level to {}", path.display()),
+        Err(err) => error!("could not save level to {}: {}", path.display(), err),
+    }
+}
@@ -1,78 +1,510 @@
 use crate::{
-    block_type::{BLOCK_HEIGHT, BLOCK_SIZE},
-    health::Health,
-    player::PLAYER_SIZE,
-    terrain::MAP_LEN,
+    block_type::{BlockType, BLOCK_HEIGHT},
+    config::GameConfig,
+    controller::{CharacterController, ControllerBundle, ControllerInput, ControllerStep},
+    enemy_kind::{EnemyKind, EnemyKinds, Movement},
+    fireball::{Faction, SpawnFireballEvent, ENEMY_FIREBALL_RADIUS},
+    ground::Grounded,
+    health::{Armor, DiedEvent, Health},
+    input::InputFrame,
+    level::LevelNumber,
+    level_file::{CurrentLevel, LevelFile},
+    player::Player,
+    state::AppState,
+    terrain::{ChunkMember, ChunkSpawnedEvent, FellInPitEvent, Terrain, TerrainSeed, MAP_LEN},
     utils::Layers,
 };
+use std::time::Duration;
+
 use bevy::prelude::*;
 use bevy_prototype_lyon::prelude::*;
 use heron::prelude::*;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng};
+use serde::{Deserialize, Serialize};
 
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_enemies);
+        app.add_system(spawn_enemies)
+            .add_system(apply_enemy_config)
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(enemy_think.before(ControllerStep))
+                    .with_system(enemy_hop.after(enemy_think).before(ControllerStep))
+                    .with_system(enemy_fire.after(enemy_think))
+                    .with_system(despawn_dead_enemies),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct Enemy {
+    /// Block it was placed on
+    pub block: u32,
+}
+
+/// Decides what an enemy is doing and moves it accordingly
+#[derive(Component)]
+pub struct EnemyBrain {
+    pub state: BrainState,
+    /// Left and right x positions the enemy patrols between
+    pub patrol: (f32, f32),
+    /// `1.0` when patrolling to the right, `-1.0` to the left
+    pub heading: f32,
+    /// Time left to wait before patrolling again
+    pub idle: Timer,
+    /// Health below which the enemy runs away
+    pub flee_health: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BrainState {
+    Idle,
+    Patrol,
+    Chase,
+    Flee,
+}
+
+impl EnemyBrain {
+    /// Patrols either side of `x`, heading right if `heading` is `1.0` or left if `-1.0`
+    pub fn new(config: &GameConfig, kind: &EnemyKind, x: f32, heading: f32) -> Self {
+        Self {
+            state: BrainState::Patrol,
+            patrol: (x - config.enemy.patrol_range, x + config.enemy.patrol_range),
+            heading,
+            idle: Timer::from_seconds(config.enemy.idle_time, false),
+            flee_health: kind.health * config.enemy.flee_below,
+        }
     }
 }
 
+/// Lets an enemy fire fireballs at the player while chasing them
 #[derive(Component)]
-pub struct Enemy;
-
-fn spawn_enemies(mut commands: Commands) {
-    let enemy_count = MAP_LEN / 25;
-
-    for i in 0..enemy_count {
-        commands
-            .spawn_bundle(load_enemy_entity(i as f32, enemy_count as f32))
-            .insert(Health(75.0))
-            .insert(RigidBody::Dynamic)
-            .insert(Velocity::from_linear(Vec3::X * 0.0))
-            .insert(Acceleration::default())
-            .insert(CollisionShape::Sphere {
-                radius: PLAYER_SIZE,
-            })
-            .insert(PhysicMaterial {
-                restitution: 0.35,
-                friction: 0.0,
-                density: PLAYER_SIZE * 5.0,
-            })
-            .insert(
-                CollisionLayers::none()
-                    .with_group(Layers::Enemy)
-                    .with_masks(&[Layers::Player, Layers::Level, Layers::Fireball]),
-            )
-            .insert(Enemy);
+pub struct RangedAttack {
+    pub cooldown: Timer,
+}
+
+/// Health taken from the player when they touch the enemy
+#[derive(Component)]
+pub struct ContactDamage(pub f32);
+
+/// Makes an enemy jump at a fixed interval
+#[derive(Component)]
+pub struct Hop {
+    pub timer: Timer,
+}
+
+/// Keeps enemy placement independent from the other seeded rngs
+pub(crate) const ENEMY_SALT: u64 = 0x0065_6E65_6D69_6573;
+
+/// Blocks per enemy on the first level
+const ENEMY_SPACING: usize = 25;
+/// Each later level has enemies this many blocks closer together, down to `MIN_ENEMY_SPACING`
+const SPACING_PER_LEVEL: usize = 3;
+const MIN_ENEMY_SPACING: usize = 10;
+/// Each later level spawns kinds as if this much further along the level
+const PROGRESS_PER_LEVEL: f32 = 0.25;
+/// No enemies are placed this close to the player's spawn point
+const SAFE_BLOCKS: u32 = 20;
+
+/// Keeps enemies already in the world up to date with the config as it is edited
+fn apply_enemy_config(
+    config: Res<GameConfig>,
+    kinds: Res<EnemyKinds>,
+    mut enemy_query: Query<
+        (
+            &Name,
+            &mut CharacterController,
+            &mut EnemyBrain,
+            Option<&mut RangedAttack>,
+        ),
+        With<Enemy>,
+    >,
+) {
+    if !config.is_changed() {
+        return;
+    }
+
+    for (name, mut controller, mut brain, ranged) in enemy_query.iter_mut() {
+        // Speed and jump are set by the kind
+        *controller = CharacterController {
+            max_speed: controller.max_speed,
+            jump_speed: controller.jump_speed,
+            ..config.enemy.controller.clone()
+        };
+
+        let centre = (brain.patrol.0 + brain.patrol.1) / 2.0;
+        brain.patrol = (
+            centre - config.enemy.patrol_range,
+            centre + config.enemy.patrol_range,
+        );
+        brain
+            .idle
+            .set_duration(Duration::from_secs_f32(config.enemy.idle_time));
+
+        if let Some(kind) = kinds.kinds.iter().find(|kind| kind.name == name.as_str()) {
+            brain.flee_health = kind.health * config.enemy.flee_below;
+        }
+
+        if let Some(mut ranged) = ranged {
+            ranged
+                .cooldown
+                .set_duration(Duration::from_secs_f32(config.enemy.fire_interval));
+        }
+    }
+}
+
+fn spawn_enemies(
+    mut commands: Commands,
+    seed: Res<TerrainSeed>,
+    config: Res<GameConfig>,
+    kinds: Res<EnemyKinds>,
+    level: Res<CurrentLevel>,
+    number: Res<LevelNumber>,
+    terrain: Res<Terrain>,
+    mut events: EventReader<ChunkSpawnedEvent>,
+) {
+    for ev in events.iter() {
+        if ev.restored {
+            continue;
+        }
+
+        let mut rng = seed.rng_at(ENEMY_SALT, ev.chunk);
+
+        let spawns = match &level.0 {
+            Some(file) => level_enemies(&kinds, file, ev),
+            None => random_enemies(&mut rng, &kinds, *number, &ev.blocks, ev.first_block),
+        };
+
+        for (block, kind) in spawns {
+            place_enemy(&mut commands, &mut rng, &config, &terrain, kind, ev, block);
+        }
+    }
+}
+
+/// The enemies a level file puts in the chunk
+fn level_enemies<'a>(
+    kinds: &'a EnemyKinds,
+    file: &LevelFile,
+    chunk: &ChunkSpawnedEvent,
+) -> Vec<(u32, &'a EnemyKind)> {
+    let mut spawns = Vec::new();
+
+    for spawn in file.enemies.iter() {
+        let in_chunk = spawn
+            .block
+            .checked_sub(chunk.first_block)
+            .map_or(false, |i| (i as usize) < chunk.surface.len());
+
+        if !in_chunk {
+            continue;
+        }
+
+        match kinds.kinds.iter().find(|kind| kind.name == spawn.kind) {
+            Some(kind) => spawns.push((spawn.block, kind)),
+            None => warn!("{}: no enemy kind named {}", file.name, spawn.kind),
+        }
+    }
+
+    spawns
+}
+
+/// Picks a block and a kind for an enemy somewhere in every few of `blocks`,
+/// closer together on later levels
+pub(crate) fn random_enemies<'a>(
+    rng: &mut StdRng,
+    kinds: &'a EnemyKinds,
+    number: LevelNumber,
+    blocks: &[BlockType],
+    first_block: u32,
+) -> Vec<(u32, &'a EnemyKind)> {
+    let spacing = ENEMY_SPACING
+        .saturating_sub(number.0 as usize * SPACING_PER_LEVEL)
+        .max(MIN_ENEMY_SPACING);
+    let head_start = number.0 as f32 * PROGRESS_PER_LEVEL;
+    let mut spawns = Vec::new();
+
+    for (i, group) in blocks.chunks(spacing).enumerate() {
+        let offset = rng.gen_range(0..group.len());
+        let block = first_block + (i * spacing + offset) as u32;
+
+        if block < SAFE_BLOCKS || !group[offset].is_walkable() {
+            continue;
+        }
+
+        // Harder kinds become more common towards the end of the level and on
+        // later levels, and endless levels stay at their hardest
+        let progress = block as f32 / MAP_LEN as f32 + head_start;
+        match kinds.choose(rng, progress) {
+            Some(kind) => spawns.push((block, kind)),
+            None => warn!("no enemy kind can spawn at block {}", block),
+        }
+    }
+
+    spawns
+}
+
+/// Spawns an enemy standing on `block`, or flying above it, facing a random way.
+/// Enemies already killed there stay dead.
+fn place_enemy(
+    commands: &mut Commands,
+    rng: &mut StdRng,
+    config: &GameConfig,
+    terrain: &Terrain,
+    kind: &EnemyKind,
+    chunk: &ChunkSpawnedEvent,
+    block: u32,
+) {
+    let ground = chunk.surface[(block - chunk.first_block) as usize];
+    let translation = enemy_position(kind, ground);
+    // Drawn even for dead enemies so the rest of the chunk is placed the same way
+    let heading = if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+
+    if terrain.enemy_killed(chunk.chunk, block) {
+        return;
+    }
+
+    let brain = EnemyBrain::new(config, kind, translation.x, heading);
+    spawn_enemy(
+        commands,
+        config,
+        kind,
+        chunk.chunk,
+        block,
+        translation,
+        brain,
+    );
+}
+
+pub(crate) fn spawn_enemy(
+    commands: &mut Commands,
+    config: &GameConfig,
+    kind: &EnemyKind,
+    chunk: u32,
+    block: u32,
+    translation: Vec3,
+    brain: EnemyBrain,
+) -> Entity {
+    let mut enemy = commands.spawn_bundle(load_enemy_entity(kind, translation));
+
+    if kind.ranged {
+        enemy.insert(RangedAttack {
+            cooldown: Timer::from_seconds(config.enemy.fire_interval, true),
+        });
+    }
+
+    let mut controller = config.enemy.controller.clone();
+    controller.max_speed = kind.speed;
+
+    match kind.movement {
+        Movement::Walk => {
+            enemy
+                .insert(RigidBody::Dynamic)
+                .insert(Grounded::new(kind.size * 1.5));
+        }
+        Movement::Hop { impulse, interval } => {
+            controller.jump_speed = impulse;
+
+            enemy
+                .insert(RigidBody::Dynamic)
+                .insert(Grounded::new(kind.size * 1.5))
+                .insert(Hop {
+                    timer: Timer::from_seconds(interval, true),
+                });
+        }
+        Movement::Fly { .. } => {
+            enemy.insert(RigidBody::KinematicVelocityBased);
+        }
+    }
+
+    enemy
+        .insert(Name::new(kind.name.clone()))
+        .insert(brain)
+        .insert_bundle(ControllerBundle::new(controller))
+        .insert(Health(kind.health))
+        .insert(Armor(kind.armor))
+        .insert(kind.resistances)
+        .insert(ContactDamage(kind.contact_damage))
+        .insert(Velocity::from_linear(Vec3::X * 0.0))
+        .insert(Acceleration::default())
+        .insert(CollisionShape::Sphere { radius: kind.size })
+        .insert(PhysicMaterial {
+            restitution: 0.35,
+            friction: 0.0,
+            density: kind.density,
+        })
+        .insert(
+            CollisionLayers::none()
+                .with_group(Layers::Enemy)
+                .with_masks(&[
+                    Layers::Player,
+                    Layers::Level,
+                    Layers::Fireball,
+                    Layers::Platform,
+                    Layers::Trigger,
+                ]),
+        )
+        .insert(ChunkMember(chunk))
+        .insert(Enemy { block })
+        .id()
+}
+
+fn enemy_think(
+    mut query: Query<(&mut EnemyBrain, &Health, &Transform, &mut ControllerInput)>,
+    player_query: Query<&Transform, With<Player>>,
+    config: Res<GameConfig>,
+    input: Res<InputFrame>,
+) {
+    let player = match player_query.get_single() {
+        Ok(player) => player.translation.truncate(),
+        Err(_) => return,
+    };
+    let delta = Duration::from_secs_f32(input.delta);
+
+    for (mut brain, health, transform, mut controller) in query.iter_mut() {
+        let position = transform.translation.truncate();
+        let sees_player = position.distance(player) <= config.enemy.detection_radius;
+
+        brain.state = match brain.state {
+            _ if sees_player && health.0 < brain.flee_health => BrainState::Flee,
+            _ if sees_player => BrainState::Chase,
+            // Lost the player, go back to walking around
+            BrainState::Chase | BrainState::Flee => BrainState::Patrol,
+            BrainState::Idle => {
+                brain.idle.tick(delta);
+
+                if brain.idle.finished() {
+                    brain.heading = -brain.heading;
+                    BrainState::Patrol
+                } else {
+                    BrainState::Idle
+                }
+            }
+            BrainState::Patrol => {
+                let (left, right) = brain.patrol;
+                let reached_end = (brain.heading < 0.0 && position.x <= left)
+                    || (brain.heading > 0.0 && position.x >= right);
+
+                if reached_end {
+                    brain.idle.reset();
+                    BrainState::Idle
+                } else {
+                    BrainState::Patrol
+                }
+            }
+        };
+
+        let towards_player = (player.x - position.x).signum();
+
+        controller.movement = match brain.state {
+            BrainState::Idle => 0.0,
+            BrainState::Patrol => brain.heading,
+            BrainState::Chase => towards_player,
+            BrainState::Flee => -towards_player,
+        };
     }
 }
 
-fn load_enemy_entity(index: f32, count: f32) -> impl Bundle {
+/// Removes enemies that were killed or fell into a pit, and keeps them from
+/// coming back when their chunk streams in again
+fn despawn_dead_enemies(
+    mut commands: Commands,
+    mut terrain: ResMut<Terrain>,
+    query: Query<(&Enemy, &ChunkMember)>,
+    mut died: EventReader<DiedEvent>,
+    mut fell: EventReader<FellInPitEvent>,
+) {
+    let gone = died
+        .iter()
+        .map(|ev| ev.entity)
+        .chain(fell.iter().map(|ev| ev.0));
+
+    for entity in gone {
+        if let Ok((enemy, member)) = query.get(entity) {
+            terrain.record(member.0).enemies.insert(enemy.block);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn enemy_hop(mut query: Query<(&mut Hop, &mut ControllerInput)>, input: Res<InputFrame>) {
+    let delta = Duration::from_secs_f32(input.delta);
+
+    for (mut hop, mut controller) in query.iter_mut() {
+        hop.timer.tick(delta);
+        controller.jump = hop.timer.just_finished();
+    }
+}
+
+fn enemy_fire(
+    mut query: Query<(&EnemyBrain, &mut RangedAttack, &Transform, &CollisionShape)>,
+    player_query: Query<&Transform, With<Player>>,
+    mut events: EventWriter<SpawnFireballEvent>,
+    config: Res<GameConfig>,
+    input: Res<InputFrame>,
+) {
+    let player = match player_query.get_single() {
+        Ok(player) => player.translation,
+        Err(_) => return,
+    };
+    let delta = Duration::from_secs_f32(input.delta);
+
+    for (brain, mut attack, transform, shape) in query.iter_mut() {
+        attack.cooldown.tick(delta);
+
+        let in_range =
+            transform.translation.truncate().distance(player.truncate()) <= config.enemy.fire_range;
+
+        if brain.state == BrainState::Chase && in_range && attack.cooldown.just_finished() {
+            let size = match shape {
+                CollisionShape::Sphere { radius } => *radius,
+                _ => 0.0,
+            };
+
+            // Fired from just outside the enemy, towards the player, so the shot
+            // doesn't start inside the enemy or the ground under it
+            let direction = (player - transform.translation)
+                .truncate()
+                .normalize_or_zero();
+            let offset = direction * (size + ENEMY_FIREBALL_RADIUS * 2.0);
+
+            events.send(SpawnFireballEvent {
+                spawn_point: transform.translation + offset.extend(0.0),
+                target: player,
+                faction: Faction::Enemy,
+            });
+        }
+    }
+}
+
+/// Where an enemy of `kind` starts out over the block with its surface at `ground`
+fn enemy_position(kind: &EnemyKind, ground: Vec2) -> Vec3 {
+    let y = match kind.movement {
+        Movement::Fly { height } => ground.y + height,
+        _ => ground.y + 0.3,
+    };
+
+    Vec3::new(ground.x, y, 850.0)
+}
+
+fn load_enemy_entity(kind: &EnemyKind, translation: Vec3) -> ShapeBundle {
     let shape = shapes::RegularPolygon {
-        sides: 20,
-        feature: shapes::RegularPolygonFeature::Radius(PLAYER_SIZE / 2.0),
+        sides: kind.sides,
+        feature: shapes::RegularPolygonFeature::Radius(kind.size / 2.0),
         ..shapes::RegularPolygon::default()
     };
-    let mut rng = rand::thread_rng();
-    let range = (
-        Vec2::splat(BLOCK_SIZE * 5.0),
-        Vec2::splat((MAP_LEN as f32 * BLOCK_SIZE) - (BLOCK_SIZE * 5.0)),
-    );
-
-    let noise = rng.gen::<f32>() * 10.0;
-    let x = range.0.lerp(range.1, index / count).x + (noise * BLOCK_SIZE);
 
-    let loc = Vec3::new(x, 0.75, 850.0);
+    let color = kind.color();
 
     GeometryBuilder::build_as(
         &shape,
         DrawMode::Outlined {
-            fill_mode: FillMode::color(Color::CYAN),
-            outline_mode: StrokeMode::new(Color::CYAN, BLOCK_HEIGHT),
+            fill_mode: FillMode::color(color),
+            outline_mode: StrokeMode::new(color, BLOCK_HEIGHT),
         },
         Transform {
-            translation: loc,
+            translation,
             ..default()
         },
     )
@@ -0,0 +1,137 @@
+use bevy::{prelude::*, reflect::TypeUuid};
+use rand::{distributions::WeightedIndex, rngs::StdRng, Rng};
+use serde::Deserialize;
+
+use crate::{
+    config::{add_ron_asset, RonAsset},
+    health::Resistances,
+};
+
+pub struct EnemyKindPlugin;
+
+impl Plugin for EnemyKindPlugin {
+    fn build(&self, app: &mut App) {
+        add_ron_asset::<EnemyKinds>(app);
+    }
+}
+
+/// Every kind of enemy the level can spawn, loaded from `assets/enemies.kinds.ron`
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "63ea4f8b-18f3-4673-a821-6df1b1da4e3b"]
+pub struct EnemyKinds {
+    pub kinds: Vec<EnemyKind>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EnemyKind {
+    pub name: String,
+    /// Sides of the polygon drawn for the enemy
+    pub sides: usize,
+    /// Radius of the collider
+    pub size: f32,
+    pub color: (f32, f32, f32),
+    pub health: f32,
+    #[serde(default)]
+    pub armor: f32,
+    #[serde(default)]
+    pub resistances: Resistances,
+    /// Health taken from the player on contact
+    pub contact_damage: f32,
+    pub density: f32,
+    pub speed: f32,
+    pub movement: Movement,
+    /// Fires at the player while chasing them
+    pub ranged: bool,
+    /// Weight at the start and at the end of the level
+    pub spawn_weight: (f32, f32),
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum Movement {
+    /// Walks along the ground
+    Walk,
+    /// Walks and jumps with `impulse` upwards speed every `interval` seconds
+    Hop { impulse: f32, interval: f32 },
+    /// Floats `height` above its spawn point, unaffected by gravity
+    Fly { height: f32 },
+}
+
+impl EnemyKind {
+    pub fn color(&self) -> Color {
+        let (r, g, b) = self.color;
+        Color::rgb(r, g, b)
+    }
+
+    /// Spawn weight at `progress` along the level, from `0.0` to `1.0`
+    pub fn weight(&self, progress: f32) -> f32 {
+        let (start, end) = self.spawn_weight;
+        (start + (end - start) * progress.clamp(0.0, 1.0)).max(0.0)
+    }
+}
+
+impl EnemyKinds {
+    /// Picks a kind by its spawn weight at `progress` along the level.
+    /// Returns `None` if no kind can spawn there.
+    pub fn choose(&self, rng: &mut StdRng, progress: f32) -> Option<&EnemyKind> {
+        let weights = self.kinds.iter().map(|kind| kind.weight(progress));
+        let index = WeightedIndex::new(weights).ok()?;
+
+        Some(&self.kinds[rng.sample(index)])
+    }
+}
+
+impl Default for EnemyKinds {
+    fn default() -> Self {
+        ron::de::from_str(include_str!("../assets/enemies.kinds.ron"))
+            .expect("embedded enemy kinds are invalid")
+    }
+}
+
+impl RonAsset for EnemyKinds {
+    const PATH: &'static str = "enemies.kinds.ron";
+    const EXTENSIONS: &'static [&'static str] = &["kinds.ron"];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn embedded_kinds_parse() {
+        let kinds = EnemyKinds::default();
+
+        assert!(!kinds.kinds.is_empty());
+        assert!(kinds.kinds.iter().all(|kind| kind.health > 0.0));
+    }
+
+    #[test]
+    fn choose_follows_progress() {
+        let kinds = EnemyKinds {
+            kinds: vec![kind("early", (1.0, 0.0)), kind("late", (0.0, 1.0))],
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert_eq!(kinds.choose(&mut rng, 0.0).unwrap().name, "early");
+        assert_eq!(kinds.choose(&mut rng, 1.0).unwrap().name, "late");
+        assert!(EnemyKinds { kinds: vec![] }.choose(&mut rng, 0.5).is_none());
+    }
+
+    fn kind(name: &str, spawn_weight: (f32, f32)) -> EnemyKind {
+        EnemyKind {
+            name: name.to_string(),
+            sides: 3,
+            size: 0.05,
+            color: (1.0, 1.0, 1.0),
+            health: 1.0,
+            armor: 0.0,
+            resistances: Resistances::default(),
+            contact_damage: 1.0,
+            density: 1.0,
+            speed: 1.0,
+            movement: Movement::Walk,
+            ranged: false,
+            spawn_weight,
+        }
+    }
+}
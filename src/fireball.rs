@@ -1,14 +1,25 @@
+use std::time::Duration;
+
 use bevy::{prelude::*, render::camera::RenderTarget};
 use heron::{Acceleration, CollisionLayers, CollisionShape, RigidBody, Velocity};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     assets::GameAssets,
     camera::MainCamera,
+    config::GameConfig,
     hud::UpdatePepperCountEvent,
+    input::InputFrame,
+    level::RestartLevelEvent,
     player::{Player, PLAYER_SIZE},
+    state::AppState,
     utils::Layers,
 };
 
+/// Radius of an enemy's fireball, well under the height of the smallest walking
+/// enemy's centre so shots fired from it start clear of the ground
+pub const ENEMY_FIREBALL_RADIUS: f32 = PLAYER_SIZE / 4.0;
+
 pub struct FireballPlugin;
 
 impl Plugin for FireballPlugin {
@@ -17,19 +28,71 @@ impl Plugin for FireballPlugin {
             .insert_resource(PepperTimer {
                 timer: Timer::from_seconds(1.0, true),
             })
-            .add_system(create_fireball)
-            .add_system(spawn_fireballs)
-            .add_system(despawn_fireball);
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(create_fireball)
+                    .with_system(spawn_fireballs)
+                    .with_system(despawn_fireball),
+            )
+            .add_system(restart_fireballs);
     }
 }
 
 pub struct SpawnFireballEvent {
-    spawn_point: Vec3,
-    target: Vec3,
+    pub spawn_point: Vec3,
+    pub target: Vec3,
+    pub faction: Faction,
+}
+
+/// Side a fireball was fired by, it only hurts the other side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Faction {
+    Player,
+    Enemy,
+}
+
+impl Faction {
+    fn collision_layers(&self) -> CollisionLayers {
+        match self {
+            Faction::Player => CollisionLayers::none()
+                .with_group(Layers::Fireball)
+                .with_masks(&[Layers::Enemy]),
+            Faction::Enemy => CollisionLayers::none()
+                .with_group(Layers::EnemyFireball)
+                .with_masks(&[Layers::Player, Layers::Level]),
+        }
+    }
+
+    fn collision_shape(&self) -> CollisionShape {
+        match self {
+            Faction::Player => CollisionShape::Capsule {
+                half_segment: PLAYER_SIZE,
+                radius: PLAYER_SIZE * 2.0,
+            },
+            Faction::Enemy => CollisionShape::Sphere {
+                radius: ENEMY_FIREBALL_RADIUS,
+            },
+        }
+    }
+
+    fn sprite(&self) -> Sprite {
+        let (color, size) = match self {
+            Faction::Player => (Color::WHITE, PLAYER_SIZE * 2.0),
+            Faction::Enemy => (Color::PURPLE, ENEMY_FIREBALL_RADIUS * 4.0),
+        };
+
+        Sprite {
+            color,
+            custom_size: Some(Vec2::splat(size)),
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Component)]
-pub struct Fireball;
+pub struct Fireball {
+    pub faction: Faction,
+}
 
 /// Tracks how long a pepper has been used
 pub struct PepperTimer {
@@ -37,31 +100,27 @@ pub struct PepperTimer {
 }
 
 fn create_fireball(
-    mouse: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
-    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    input: Res<InputFrame>,
     player_query: Query<&Transform, With<Player>>,
     mut timer: ResMut<PepperTimer>,
     mut event_writer: EventWriter<SpawnFireballEvent>,
-    time: Res<Time>,
 ) {
-    if mouse.pressed(MouseButton::Left) || mouse.just_pressed(MouseButton::Left) {
+    if let Some(position) = input.fire {
         let player = player_query.single();
 
-        if let Some(position) = get_world_coords(&windows, &q_camera) {
-            let ev = SpawnFireballEvent {
-                spawn_point: player.translation,
-                target: position.extend(900.0),
-            };
+        let ev = SpawnFireballEvent {
+            spawn_point: player.translation,
+            target: position.extend(900.0),
+            faction: Faction::Player,
+        };
 
-            timer.timer.tick(time.delta());
-            event_writer.send(ev);
-        }
+        timer.timer.tick(Duration::from_secs_f32(input.delta));
+        event_writer.send(ev);
     }
 }
 
 /// Translates mouse coordinates to world space
-fn get_world_coords(
+pub(crate) fn get_world_coords(
     wnds: &Res<Windows>,
     q_camera: &Query<(&Camera, &GlobalTransform), With<MainCamera>>,
 ) -> Option<Vec2> {
@@ -102,10 +161,11 @@ fn get_world_coords(
 fn spawn_fireballs(
     mut commands: Commands,
     mut player_query: Query<&mut Player>,
-    assets: Res<GameAssets>,
+    assets: Option<Res<GameAssets>>,
     mut events: EventReader<SpawnFireballEvent>,
     mut update_text: EventWriter<UpdatePepperCountEvent>,
     timer: ResMut<PepperTimer>,
+    config: Res<GameConfig>,
 ) {
     let mut player = player_query.single_mut();
 
@@ -114,55 +174,67 @@ fn spawn_fireballs(
         update_text.send(UpdatePepperCountEvent(player.peppers));
     }
 
-    if timer.timer.times_finished() < player.peppers {
-        for ev in events.iter() {
-            let mut bundle = SpriteBundle {
-                sprite: Sprite {
-                    custom_size: Some(Vec2::splat(PLAYER_SIZE * 2.0)),
-                    ..Default::default()
-                },
-                texture: assets.fireball.clone_weak(),
-                transform: Transform {
-                    translation: ev.spawn_point,
-                    ..Default::default()
-                },
-                ..Default::default()
-            };
-
-            let dist = ev
-                .target
-                .truncate()
-                .distance(bundle.transform.translation.truncate());
-
-            let impulse = dist * 5.0;
-            let dy = ev.target.y - bundle.transform.translation.y;
-            let dx = ev.target.x - bundle.transform.translation.x;
-            let angle = f32::atan2(dy, dx);
-
-            bundle.transform.rotation = Quat::from_rotation_z(angle);
-
-            let fireball_vec = Vec3::new(angle.cos() * impulse, angle.sin() * impulse, 900.0);
-
-            commands
-                .spawn_bundle(bundle)
-                .insert(RigidBody::Dynamic)
-                .insert(Velocity::from_linear(fireball_vec))
-                .insert(Acceleration::default())
-                .insert(CollisionShape::Capsule {
-                    half_segment: (PLAYER_SIZE),
-                    radius: (PLAYER_SIZE * 2.0),
-                })
-                .insert(
-                    CollisionLayers::none()
-                        .with_group(Layers::Fireball)
-                        .with_masks(&[Layers::Enemy]),
-                )
-                .insert(Fireball)
-                .insert(Name::new("fireball"));
+    let has_peppers = timer.timer.times_finished() < player.peppers;
+
+    for ev in events.iter() {
+        // Enemies don't need peppers to fire
+        if ev.faction == Faction::Player && !has_peppers {
+            continue;
         }
+
+        let dist = ev.target.truncate().distance(ev.spawn_point.truncate());
+
+        let impulse = dist * config.fireball.impulse;
+        let dy = ev.target.y - ev.spawn_point.y;
+        let dx = ev.target.x - ev.spawn_point.x;
+        let angle = f32::atan2(dy, dx);
+
+        let transform = Transform {
+            translation: ev.spawn_point,
+            rotation: Quat::from_rotation_z(angle),
+            ..Default::default()
+        };
+        let fireball_vec = Vec3::new(angle.cos() * impulse, angle.sin() * impulse, 900.0);
+
+        spawn_fireball(
+            &mut commands,
+            assets.as_deref(),
+            transform,
+            fireball_vec,
+            ev.faction,
+        );
     }
 }
 
+/// `assets` is `None` when running headless
+pub(crate) fn spawn_fireball(
+    commands: &mut Commands,
+    assets: Option<&GameAssets>,
+    transform: Transform,
+    velocity: Vec3,
+    faction: Faction,
+) -> Entity {
+    let bundle = SpriteBundle {
+        sprite: faction.sprite(),
+        texture: assets
+            .map(|assets| assets.fireball.clone_weak())
+            .unwrap_or_default(),
+        transform,
+        ..Default::default()
+    };
+
+    commands
+        .spawn_bundle(bundle)
+        .insert(RigidBody::Dynamic)
+        .insert(Velocity::from_linear(velocity))
+        .insert(Acceleration::default())
+        .insert(faction.collision_shape())
+        .insert(faction.collision_layers())
+        .insert(Fireball { faction })
+        .insert(Name::new("fireball"))
+        .id()
+}
+
 fn despawn_fireball(mut commands: Commands, query: Query<(Entity, &Transform), With<Fireball>>) {
     for (entity, transform) in query.iter() {
         if transform.translation.y < -1.5 {
@@ -170,3 +242,20 @@ fn despawn_fireball(mut commands: Commands, query: Query<(Entity, &Transform), W
         }
     }
 }
+
+fn restart_fireballs(
+    mut commands: Commands,
+    mut timer: ResMut<PepperTimer>,
+    mut events: EventReader<RestartLevelEvent>,
+    query: Query<Entity, With<Fireball>>,
+) {
+    if events.iter().last().is_none() {
+        return;
+    }
+
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    timer.timer.reset();
+}
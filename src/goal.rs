@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use heron::prelude::*;
+
+use crate::{
+    block_type::BLOCK_SIZE,
+    level_file::CurrentLevel,
+    state::AppState,
+    stats::RunStats,
+    terrain::{ChunkMember, ChunkSpawnedEvent, LevelLength},
+    utils::Layers,
+};
+
+const POLE_HEIGHT: f32 = 0.5;
+const POLE_WIDTH: f32 = 0.02;
+const FLAG_WIDTH: f32 = 0.15;
+const FLAG_HEIGHT: f32 = 0.1;
+
+pub struct GoalPlugin;
+
+impl Plugin for GoalPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LevelCompleteEvent>()
+            .add_system(spawn_goal)
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(complete_level));
+    }
+}
+
+/// Finish line at the end of the level, touching it wins the level
+#[derive(Component)]
+pub struct Goal;
+
+/// Sent when the player reaches the goal
+pub struct LevelCompleteEvent;
+
+/// Block the goal stands on, endless levels have none
+pub fn goal_block(length: LevelLength, level: &CurrentLevel) -> Option<u32> {
+    match &level.0 {
+        Some(file) => Some(file.goal),
+        None => length.blocks().map(|blocks| blocks.saturating_sub(1)),
+    }
+}
+
+fn spawn_goal(
+    mut commands: Commands,
+    length: Res<LevelLength>,
+    level: Res<CurrentLevel>,
+    mut events: EventReader<ChunkSpawnedEvent>,
+) {
+    let goal = match goal_block(*length, &level) {
+        Some(goal) => goal,
+        None => return,
+    };
+
+    for ev in events.iter() {
+        let ground = match goal.checked_sub(ev.first_block) {
+            Some(i) if (i as usize) < ev.surface.len() => ev.surface[i as usize],
+            _ => continue,
+        };
+
+        // Pole with a flag off its top, centred so the sensor covers the pole
+        let half = POLE_HEIGHT / 2.0;
+        let flag = shapes::Polygon {
+            points: vec![
+                Vec2::new(-POLE_WIDTH / 2.0, -half),
+                Vec2::new(-POLE_WIDTH / 2.0, half),
+                Vec2::new(POLE_WIDTH / 2.0 + FLAG_WIDTH, half - FLAG_HEIGHT / 2.0),
+                Vec2::new(POLE_WIDTH / 2.0, half - FLAG_HEIGHT),
+                Vec2::new(POLE_WIDTH / 2.0, -half),
+            ],
+            closed: true,
+        };
+
+        commands
+            .spawn_bundle(GeometryBuilder::build_as(
+                &flag,
+                DrawMode::Fill(FillMode::color(Color::LIME_GREEN)),
+                Transform::from_xyz(ground.x, ground.y + half, 850.0),
+            ))
+            .insert(Name::new("Goal"))
+            .insert(Goal)
+            .insert(ChunkMember(ev.chunk))
+            .insert(RigidBody::Sensor)
+            .insert(CollisionShape::Cuboid {
+                half_extends: Vec3::new(BLOCK_SIZE / 2.0, half, 0.0),
+                border_radius: None,
+            })
+            .insert(
+                CollisionLayers::none()
+                    .with_group(Layers::Trigger)
+                    .with_mask(Layers::Player),
+            );
+    }
+}
+
+fn complete_level(
+    mut events: EventReader<LevelCompleteEvent>,
+    stats: Res<RunStats>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if events.iter().last().is_none() {
+        return;
+    }
+
+    info!("level complete with {} points: {:?}", stats.score(), *stats);
+
+    if let Err(err) = state.set(AppState::Victory) {
+        warn!("could not finish the level: {:?}", err);
+    }
+}
@@ -0,0 +1,106 @@
+use bevy::prelude::*;
+use heron::{rapier_plugin::PhysicsWorld, CollisionLayers};
+
+use crate::{input::InputFrame, state::AppState, utils::Layers};
+
+pub struct GroundPlugin;
+
+impl Plugin for GroundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::Playing).with_system(detect_ground.label(GroundCheck)),
+        );
+    }
+}
+
+/// Systems that read `Grounded` should run after this
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct GroundCheck;
+
+/// Whether an entity is standing on the level, updated every tick with a ray cast
+#[derive(Component)]
+pub struct Grounded {
+    /// How far below the entity's centre the ground is looked for
+    pub reach: f32,
+    pub on_ground: bool,
+    /// Surface normal of the ground, `Vec3::Y` while in the air
+    pub normal: Vec3,
+    /// Seconds since the entity was last on the ground
+    pub airtime: f32,
+}
+
+impl Grounded {
+    pub fn new(reach: f32) -> Self {
+        Self {
+            reach,
+            on_ground: false,
+            normal: Vec3::Y,
+            airtime: f32::INFINITY,
+        }
+    }
+
+    /// On the ground, or left it no more than `coyote_time` seconds ago
+    pub fn can_jump(&self, coyote_time: f32) -> bool {
+        self.on_ground || self.airtime <= coyote_time
+    }
+
+    /// Stops a jump from being taken again until the entity lands
+    pub fn leave(&mut self) {
+        self.on_ground = false;
+        self.airtime = f32::INFINITY;
+    }
+}
+
+fn detect_ground(
+    mut query: Query<(Entity, &Transform, &CollisionLayers, &mut Grounded)>,
+    physics: PhysicsWorld,
+    input: Res<InputFrame>,
+) {
+    for (entity, transform, own_layers, mut grounded) in query.iter_mut() {
+        // Platforms only count as ground while the entity would land on them
+        let mut layers = CollisionLayers::none()
+            .with_groups(&[Layers::Player, Layers::Enemy])
+            .with_mask(Layers::Level);
+        if own_layers.contains_mask(Layers::Platform) {
+            layers = layers.with_mask(Layers::Platform);
+        }
+
+        let hit = physics.ray_cast_with_filter(
+            transform.translation,
+            Vec3::new(0.0, -grounded.reach, 0.0),
+            true,
+            layers,
+            |other| other != entity,
+        );
+
+        match hit {
+            Some(hit) => {
+                grounded.on_ground = true;
+                grounded.normal = hit.normal;
+                grounded.airtime = 0.0;
+            }
+            None => {
+                grounded.on_ground = false;
+                grounded.normal = Vec3::Y;
+                grounded.airtime += input.delta;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coyote_time_allows_late_jumps() {
+        let mut grounded = Grounded::new(1.0);
+        assert!(!grounded.can_jump(0.1));
+
+        grounded.airtime = 0.05;
+        assert!(grounded.can_jump(0.1));
+
+        grounded.leave();
+        assert!(!grounded.can_jump(0.1));
+    }
+}
@@ -0,0 +1,266 @@
+use bevy::{ecs::event::Events, prelude::*};
+use heron::{CollisionData, CollisionEvent, CollisionLayers, Velocity};
+
+use crate::{
+    enemy::Enemy,
+    fireball::Fireball,
+    goal::LevelCompleteEvent,
+    health::{DamageEvent, DamageKind, Health},
+    input::{InputFrame, InputSource, Recorder, Replay},
+    input_map::InputMap,
+    level::{NextLevelEvent, RestartLevelEvent},
+    level_file::{CurrentLevel, LevelFile},
+    pepper::Pepper,
+    player::Player,
+    plugins::HeadlessPlugins,
+    save::SaveStorage,
+    snapshot::{LoadSnapshotEvent, SaveSnapshotEvent, Snapshot},
+    state::AppState,
+    terrain::{Chunk, LevelLength, TerrainSeed, MAP_LEN},
+};
+
+/// Steps the game without a window, for tests and CI
+pub struct Simulation {
+    pub app: App,
+}
+
+/// Copy of the player's state at one point of a simulation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerState {
+    pub translation: Vec3,
+    pub velocity: Vec3,
+    pub health: f32,
+    pub peppers: u32,
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        Self::with_input(seed, LevelLength::Blocks(MAP_LEN), None, Vec::new())
+    }
+
+    pub fn endless(seed: u64) -> Self {
+        Self::with_input(seed, LevelLength::Endless, None, Vec::new())
+    }
+
+    /// Plays a hand-made level instead of generated terrain
+    pub fn with_level(level: LevelFile) -> Self {
+        Self::with_input(0, LevelLength::Blocks(MAP_LEN), Some(level), Vec::new())
+    }
+
+    /// Plays a recorded run from the start
+    pub fn from_replay(replay: &Replay) -> Self {
+        Self::with_input(
+            replay.seed,
+            LevelLength::Blocks(MAP_LEN),
+            None,
+            replay.frames.clone(),
+        )
+    }
+
+    fn with_input(
+        seed: u64,
+        length: LevelLength,
+        level: Option<LevelFile>,
+        frames: Vec<InputFrame>,
+    ) -> Self {
+        let mut app = App::new();
+
+        app.insert_resource(TerrainSeed(seed))
+            .insert_resource(length)
+            .insert_resource(CurrentLevel(level))
+            .insert_resource(InputSource::Scripted(frames.into()))
+            .insert_resource(Recorder::in_memory())
+            .insert_resource(InputMap::default())
+            .insert_resource(SaveStorage::Memory(default()))
+            .add_plugins(HeadlessPlugins);
+
+        // Runs the startup systems
+        app.update();
+
+        Self { app }
+    }
+
+    pub fn step(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.app.update();
+        }
+    }
+
+    /// Adds frames to play after the ones already queued, one per tick
+    pub fn queue(&mut self, frames: impl IntoIterator<Item = InputFrame>) {
+        if let InputSource::Scripted(queue) = self.app.world.resource_mut::<InputSource>().as_mut()
+        {
+            queue.extend(frames);
+        }
+    }
+
+    /// Every frame played so far as a replay
+    pub fn replay(&self) -> Replay {
+        let seed = self.app.world.resource::<TerrainSeed>();
+        self.app.world.resource::<Recorder>().replay(seed)
+    }
+
+    pub fn world(&mut self) -> &mut World {
+        &mut self.app.world
+    }
+
+    pub fn player(&mut self) -> PlayerState {
+        let mut query = self
+            .app
+            .world
+            .query::<(&Player, &Health, &Transform, &Velocity)>();
+        let (player, health, transform, velocity) = query
+            .iter(&self.app.world)
+            .next()
+            .expect("simulation has no player");
+
+        PlayerState {
+            translation: transform.translation,
+            velocity: velocity.linear,
+            health: health.0,
+            peppers: player.peppers,
+        }
+    }
+
+    pub fn count<T: Component>(&mut self) -> usize {
+        self.app
+            .world
+            .query_filtered::<Entity, With<T>>()
+            .iter(&self.app.world)
+            .count()
+    }
+
+    pub fn entities<T: Component>(&mut self) -> Vec<Entity> {
+        self.app
+            .world
+            .query_filtered::<Entity, With<T>>()
+            .iter(&self.app.world)
+            .collect()
+    }
+
+    pub fn enemies(&mut self) -> usize {
+        self.count::<Enemy>()
+    }
+
+    pub fn peppers(&mut self) -> usize {
+        self.count::<Pepper>()
+    }
+
+    pub fn fireballs(&mut self) -> usize {
+        self.count::<Fireball>()
+    }
+
+    /// Indices of the terrain chunks in the world
+    pub fn chunks(&mut self) -> Vec<u32> {
+        let mut chunks: Vec<u32> = self
+            .app
+            .world
+            .query::<&Chunk>()
+            .iter(&self.app.world)
+            .map(|chunk| chunk.0)
+            .collect();
+
+        chunks.sort_unstable();
+        chunks
+    }
+
+    /// Moves the player without going through physics
+    pub fn teleport(&mut self, translation: Vec3) {
+        let mut query = self
+            .app
+            .world
+            .query_filtered::<&mut Transform, With<Player>>();
+
+        for mut transform in query.iter_mut(&mut self.app.world) {
+            transform.translation = translation;
+        }
+    }
+
+    pub fn damage(&mut self, event: DamageEvent) {
+        self.app
+            .world
+            .resource_mut::<Events<DamageEvent>>()
+            .send(event);
+    }
+
+    /// Damages `target` as if from nothing in particular
+    pub fn hurt(&mut self, target: Entity, amount: f32, kind: DamageKind) {
+        self.damage(DamageEvent {
+            target,
+            amount,
+            source: None,
+            kind,
+        });
+    }
+
+    /// Sends the collision physics reports when `a` starts touching `b`
+    pub fn touch(&mut self, a: Entity, b: Entity) {
+        let data = |world: &World, entity| {
+            let layers = *world
+                .get::<CollisionLayers>(entity)
+                .expect("no collision layers");
+            CollisionData::new(entity, entity, layers, Vec::new())
+        };
+        let event = CollisionEvent::Started(data(&self.app.world, a), data(&self.app.world, b));
+
+        self.app
+            .world
+            .resource_mut::<Events<CollisionEvent>>()
+            .send(event);
+    }
+
+    pub fn restart(&mut self) {
+        self.app
+            .world
+            .resource_mut::<Events<RestartLevelEvent>>()
+            .send(RestartLevelEvent);
+    }
+
+    /// Acts as if the player reached the goal
+    pub fn complete_level(&mut self) {
+        self.app
+            .world
+            .resource_mut::<Events<LevelCompleteEvent>>()
+            .send(LevelCompleteEvent);
+    }
+
+    /// Picks "Next Level" on the results screen
+    pub fn next_level(&mut self) {
+        self.app
+            .world
+            .resource_mut::<Events<NextLevelEvent>>()
+            .send(NextLevelEvent);
+
+        if let Err(err) = self
+            .app
+            .world
+            .resource_mut::<State<AppState>>()
+            .set(AppState::Playing)
+        {
+            warn!("could not start the next level: {:?}", err);
+        }
+    }
+
+    pub fn state(&self) -> AppState {
+        *self.app.world.resource::<State<AppState>>().current()
+    }
+
+    /// Saves the run as if quitting from the pause menu, stepping once to write it
+    pub fn save_snapshot(&mut self) -> Snapshot {
+        self.app
+            .world
+            .resource_mut::<Events<SaveSnapshotEvent>>()
+            .send(SaveSnapshotEvent);
+        self.step(1);
+
+        Snapshot::load(self.app.world.resource::<SaveStorage>()).expect("run was not saved")
+    }
+
+    /// Carries on from `snapshot` from the next step
+    pub fn load_snapshot(&mut self, snapshot: Snapshot) {
+        self.app
+            .world
+            .resource_mut::<Events<LoadSnapshotEvent>>()
+            .send(LoadSnapshotEvent(snapshot));
+    }
+}
@@ -1,61 +1,212 @@
+use std::time::Duration;
+
 use bevy::prelude::*;
 use bevy_inspector_egui::Inspectable;
+use serde::Deserialize;
 
-use crate::{hud::UpdateHealthPointsEvent, player::Player};
+use crate::{input::InputFrame, state::AppState};
 
 pub struct HealthPlugin;
 
 impl Plugin for HealthPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<DamageEvent>()
-            .add_system(damage_enemy)
-            .add_system(damage_player);
+            .add_event::<DamagedEvent>()
+            .add_event::<DiedEvent>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(tick_invulnerability)
+                    .with_system(apply_damage.label(ApplyDamage).after(tick_invulnerability)),
+            );
     }
 }
 
-pub struct DamageEvent(pub Entity);
+/// Systems reading `DamagedEvent` or `DiedEvent` in the same frame should run after this
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct ApplyDamage;
+
+/// Asks for `amount` health to be taken from `target`
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    /// Whatever dealt the damage, if it still matters
+    pub source: Option<Entity>,
+    pub kind: DamageKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamageKind {
+    /// Touching an enemy
+    Contact,
+    Fireball,
+    /// Spikes and other parts of the level
+    Hazard,
+}
+
+/// Sent for every `DamageEvent` that took health from its target
+pub struct DamagedEvent {
+    pub target: Entity,
+    /// Health actually taken after armor and resistances
+    pub amount: f32,
+    pub source: Option<Entity>,
+    pub kind: DamageKind,
+}
+
+/// Sent once when an entity's health runs out
+pub struct DiedEvent {
+    pub entity: Entity,
+    /// Source of the killing blow
+    pub killer: Option<Entity>,
+}
 
 #[derive(Component, Inspectable)]
 pub struct Health(pub f32);
 
-fn damage_enemy(
-    mut commands: Commands,
-    mut query: Query<(Entity, &mut Health), Without<Player>>,
-    mut events: EventReader<DamageEvent>,
-) {
-    let health_damage = 0.05;
+/// Taken off every hit after resistances
+#[derive(Component, Inspectable)]
+pub struct Armor(pub f32);
 
-    for ev in events.iter() {
-        let item = query.iter_mut().find(|item| item.0 == ev.0);
+/// Fraction of each kind of damage that is ignored
+#[derive(Component, Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct Resistances {
+    pub contact: f32,
+    pub fireball: f32,
+    pub hazard: f32,
+}
 
-        if let Some((entity, mut health)) = item {
-            if health.0 <= 0.0 {
-                commands.entity(entity).despawn_recursive();
-            }
+impl Resistances {
+    fn apply(&self, amount: f32, kind: DamageKind) -> f32 {
+        let resistance = match kind {
+            DamageKind::Contact => self.contact,
+            DamageKind::Fireball => self.fireball,
+            DamageKind::Hazard => self.hazard,
+        };
 
-            health.0 -= health_damage;
-        }
+        amount * (1.0 - resistance.clamp(0.0, 1.0))
+    }
+}
+
+/// Ignores damage for `duration` seconds after every hit
+#[derive(Component)]
+pub struct Invulnerability {
+    pub duration: f32,
+    pub timer: Timer,
+}
+
+impl Invulnerability {
+    pub fn new(duration: f32) -> Self {
+        let mut timer = Timer::from_seconds(duration, false);
+        timer.tick(Duration::from_secs_f32(duration));
+
+        Self { duration, timer }
+    }
+
+    pub fn active(&self) -> bool {
+        !self.timer.finished()
+    }
+
+    /// Ignores damage for `seconds`, however long hits make it last
+    pub fn grant(&mut self, seconds: f32) {
+        self.timer = Timer::from_seconds(seconds, false);
+    }
+
+    fn start(&mut self) {
+        self.grant(self.duration);
     }
 }
 
-fn damage_player(
-    _commands: Commands,
-    mut query: Query<(Entity, &mut Health), With<Player>>,
+fn tick_invulnerability(mut query: Query<&mut Invulnerability>, input: Res<InputFrame>) {
+    let delta = Duration::from_secs_f32(input.delta);
+
+    for mut invulnerability in query.iter_mut() {
+        invulnerability.timer.tick(delta);
+    }
+}
+
+fn apply_damage(
+    mut query: Query<(
+        &mut Health,
+        Option<&Armor>,
+        Option<&Resistances>,
+        Option<&mut Invulnerability>,
+    )>,
     mut events: EventReader<DamageEvent>,
-    mut writer: EventWriter<UpdateHealthPointsEvent>,
+    mut damaged: EventWriter<DamagedEvent>,
+    mut died: EventWriter<DiedEvent>,
 ) {
-    let health_damage = 0.1;
-
     for ev in events.iter() {
-        let item = query.iter_mut().find(|item| item.0 == ev.0);
+        let (mut health, armor, resistances, mut invulnerability) = match query.get_mut(ev.target) {
+            Ok(item) => item,
+            Err(_) => continue,
+        };
+
+        // Already dead, its `DiedEvent` has been sent
+        if health.0 <= 0.0 {
+            continue;
+        }
 
-        if let Some((_entity, mut health)) = item {
-            if health.0 <= 0.0 {
-                todo!("add game over screen");
-            }
+        if matches!(&invulnerability, Some(invulnerability) if invulnerability.active()) {
+            continue;
+        }
+
+        let mut amount = ev.amount;
+
+        if let Some(resistances) = resistances {
+            amount = resistances.apply(amount, ev.kind);
+        }
 
-            health.0 -= health_damage;
-            writer.send(UpdateHealthPointsEvent(health.0));
+        if let Some(armor) = armor {
+            amount -= armor.0;
         }
+
+        if amount <= 0.0 {
+            continue;
+        }
+
+        if let Some(invulnerability) = invulnerability.as_mut() {
+            invulnerability.start();
+        }
+
+        health.0 -= amount;
+
+        damaged.send(DamagedEvent {
+            target: ev.target,
+            amount,
+            source: ev.source,
+            kind: ev.kind,
+        });
+
+        if health.0 <= 0.0 {
+            died.send(DiedEvent {
+                entity: ev.target,
+                killer: ev.source,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resistances_reduce_damage_of_their_kind() {
+        let resistances = Resistances {
+            contact: 0.5,
+            ..Default::default()
+        };
+
+        assert_eq!(resistances.apply(10.0, DamageKind::Contact), 5.0);
+        assert_eq!(resistances.apply(10.0, DamageKind::Fireball), 10.0);
+    }
+
+    #[test]
+    fn invulnerability_starts_inactive() {
+        let mut invulnerability = Invulnerability::new(1.0);
+        assert!(!invulnerability.active());
+
+        invulnerability.start();
+        assert!(invulnerability.active());
     }
 }
@@ -5,16 +5,35 @@ pub struct HudPlugin;
 
 impl Plugin for HudPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<UpdatePepperCountEvent>()
-            .add_event::<UpdateHealthPointsEvent>()
+        app.add_plugin(HudEventsPlugin)
             .add_startup_system(create_hud)
             .add_system(update_pepper_count)
-            .add_system(update_health_points);
+            .add_system(update_health_points)
+            .add_system(update_lives)
+            .add_system(update_score);
+    }
+}
+
+/// Registers the events the HUD listens to, so the game can run without the HUD
+pub struct HudEventsPlugin;
+
+impl Plugin for HudEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<UpdatePepperCountEvent>()
+            .add_event::<UpdateHealthPointsEvent>()
+            .add_event::<UpdateLivesEvent>()
+            .add_event::<UpdateScoreEvent>();
     }
 }
 
 pub struct UpdatePepperCountEvent(pub u32);
 pub struct UpdateHealthPointsEvent(pub f32);
+pub struct UpdateLivesEvent(pub u32);
+pub struct UpdateScoreEvent {
+    pub score: u32,
+    /// What the next kill is multiplied by, only shown during a combo
+    pub multiplier: u32,
+}
 
 #[derive(Component)]
 struct HudCamera;
@@ -25,6 +44,12 @@ struct PepperCount;
 #[derive(Component)]
 struct HealthPoints;
 
+#[derive(Component)]
+struct LivesCount;
+
+#[derive(Component)]
+struct Score;
+
 fn create_hud(mut commands: Commands, assets: Res<GameAssets>) {
     commands.spawn_bundle(UiCameraBundle::default());
 
@@ -93,6 +118,80 @@ fn create_hud(mut commands: Commands, assets: Res<GameAssets>) {
             ..default()
         })
         .insert(HealthPoints);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::FlexEnd,
+                position_type: PositionType::Relative,
+                margin: Rect::all(Val::Px(10.0)),
+                ..default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: "Lives: ".to_string(),
+                        style: TextStyle {
+                            font: assets.fonts.medium.clone_weak(),
+                            font_size: 25.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: "3".to_string(),
+                        style: TextStyle {
+                            font: assets.fonts.medium.clone_weak(),
+                            font_size: 25.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                ],
+                ..default()
+            },
+            ..default()
+        })
+        .insert(LivesCount);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::FlexEnd,
+                position_type: PositionType::Relative,
+                margin: Rect::all(Val::Px(10.0)),
+                ..default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: "Score: ".to_string(),
+                        style: TextStyle {
+                            font: assets.fonts.medium.clone_weak(),
+                            font_size: 25.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: "0".to_string(),
+                        style: TextStyle {
+                            font: assets.fonts.medium.clone_weak(),
+                            font_size: 25.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: "".to_string(),
+                        style: TextStyle {
+                            font: assets.fonts.medium.clone_weak(),
+                            font_size: 25.0,
+                            color: Color::ORANGE,
+                        },
+                    },
+                ],
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Score);
 }
 
 fn update_pepper_count(
@@ -116,3 +215,29 @@ fn update_health_points(
         }
     }
 }
+
+fn update_lives(
+    mut events: EventReader<UpdateLivesEvent>,
+    mut query: Query<&mut Text, With<LivesCount>>,
+) {
+    for mut text in query.iter_mut() {
+        for ev in events.iter() {
+            text.sections[1].value = format!("{}", ev.0);
+        }
+    }
+}
+
+fn update_score(
+    mut events: EventReader<UpdateScoreEvent>,
+    mut query: Query<&mut Text, With<Score>>,
+) {
+    for mut text in query.iter_mut() {
+        for ev in events.iter() {
+            text.sections[1].value = format!("{}", ev.score);
+            text.sections[2].value = match ev.multiplier {
+                0 | 1 => String::new(),
+                multiplier => format!("  x{}", multiplier),
+            };
+        }
+    }
+}
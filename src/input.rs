@@ -0,0 +1,240 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::{app::AppExit, prelude::*};
+use heron::PhysicsSteps;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::MainCamera,
+    fireball::get_world_coords,
+    input_map::{Action, InputDevices, InputMap},
+    player::Player,
+    save::SaveStorage,
+    state::AppState,
+    terrain::TerrainSeed,
+    utils::arg_value,
+};
+
+/// Length of one tick when the game runs with a fixed timestep
+pub const TICK: f32 = 1.0 / 60.0;
+
+pub struct InputFramePlugin;
+
+impl Plugin for InputFramePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<InputSource>() {
+            match Replay::from_args() {
+                Some(replay) => {
+                    info!("replaying {} frames", replay.frames.len());
+
+                    app.insert_resource(TerrainSeed(replay.seed))
+                        .insert_resource(InputSource::Scripted(replay.frames.into()))
+                        .add_startup_system(skip_menu);
+                }
+                None => {
+                    app.insert_resource(InputSource::Live);
+                }
+            }
+        }
+
+        if !app.world.contains_resource::<InputMap>() {
+            app.init_resource::<SaveStorage>();
+
+            let map = InputMap::load(app.world.resource::<SaveStorage>());
+            app.insert_resource(map);
+        }
+
+        if let Some(path) = arg_value("--record") {
+            app.insert_resource(Recorder::to_file(path.into()));
+        }
+
+        // Recordings are only reproducible if physics steps as often as the input does
+        let fixed_step = matches!(
+            app.world.resource::<InputSource>(),
+            InputSource::Scripted(_)
+        ) || app.world.contains_resource::<Recorder>();
+
+        if fixed_step {
+            app.insert_resource(PhysicsSteps::every_frame(Duration::from_secs_f32(TICK)));
+        }
+
+        app.init_resource::<InputFrame>()
+            .add_system_to_stage(CoreStage::PreUpdate, read_input)
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(record_input))
+            .add_system_to_stage(CoreStage::Last, save_recording);
+    }
+}
+
+/// Everything the player asked for during one tick
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputFrame {
+    /// Seconds of game time this frame covers
+    pub delta: f32,
+    pub left: bool,
+    pub right: bool,
+    pub jump: bool,
+    /// World position the player is firing at
+    pub fire: Option<Vec2>,
+}
+
+impl InputFrame {
+    pub fn idle() -> Self {
+        Self {
+            delta: TICK,
+            ..Default::default()
+        }
+    }
+}
+
+/// Where each tick's `InputFrame` comes from
+pub enum InputSource {
+    /// Keyboard and mouse
+    Live,
+    /// Frames from a replay file or a test, one per tick. Idles once they run out.
+    Scripted(VecDeque<InputFrame>),
+}
+
+/// A recorded run, enough to play it back exactly
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub frames: Vec<InputFrame>,
+}
+
+impl Replay {
+    /// Loads the replay passed with `--replay <path>`
+    pub fn from_args() -> Option<Self> {
+        let path = arg_value("--replay")?;
+
+        match Self::load(Path::new(&path)) {
+            Ok(replay) => Some(replay),
+            Err(err) => {
+                error!("could not load replay {}: {}", path, err);
+                None
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let text = fs::read_to_string(path)?;
+        Ok(ron::de::from_str(&text)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let text = ron::ser::to_string_pretty(self, Default::default())?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+/// Keeps every frame played so the run can be saved as a `Replay`
+pub struct Recorder {
+    /// Written to when the game exits, `None` keeps the recording in memory
+    pub path: Option<PathBuf>,
+    pub frames: Vec<InputFrame>,
+}
+
+impl Recorder {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn to_file(path: PathBuf) -> Self {
+        Self {
+            path: Some(path),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn replay(&self, seed: &TerrainSeed) -> Replay {
+        Replay {
+            seed: seed.0,
+            frames: self.frames.clone(),
+        }
+    }
+}
+
+fn skip_menu(mut state: ResMut<State<AppState>>) {
+    if let Err(err) = state.set(AppState::Playing) {
+        warn!("could not start replay: {:?}", err);
+    }
+}
+
+fn read_input(
+    mut frame: ResMut<InputFrame>,
+    mut source: ResMut<InputSource>,
+    state: Res<State<AppState>>,
+    recorder: Option<Res<Recorder>>,
+    map: Res<InputMap>,
+    devices: InputDevices,
+    windows: Res<Windows>,
+    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    q_player: Query<&Transform, With<Player>>,
+    time: Res<Time>,
+) {
+    // Scripted frames are only used up while they can affect the game
+    if *state.current() != AppState::Playing {
+        *frame = InputFrame::idle();
+        return;
+    }
+
+    *frame = match source.as_mut() {
+        InputSource::Live => InputFrame {
+            delta: match recorder {
+                Some(_) => TICK,
+                None => time.delta_seconds(),
+            },
+            left: map.pressed(Action::MoveLeft, &devices),
+            right: map.pressed(Action::MoveRight, &devices),
+            jump: map.pressed(Action::Jump, &devices),
+            fire: if map.pressed(Action::Fire, &devices) {
+                // The stick aims around the player, otherwise aim at the cursor
+                match (map.aim(&devices), q_player.get_single()) {
+                    (Some(aim), Ok(player)) => {
+                        Some(player.translation.truncate() + aim * map.aim_distance)
+                    }
+                    _ => get_world_coords(&windows, &q_camera),
+                }
+            } else {
+                None
+            },
+        },
+        InputSource::Scripted(frames) => frames.pop_front().unwrap_or_else(InputFrame::idle),
+    };
+}
+
+fn record_input(frame: Res<InputFrame>, recorder: Option<ResMut<Recorder>>) {
+    if let Some(mut recorder) = recorder {
+        recorder.frames.push(*frame);
+    }
+}
+
+fn save_recording(
+    mut exit: EventReader<AppExit>,
+    recorder: Option<Res<Recorder>>,
+    seed: Res<TerrainSeed>,
+) {
+    if exit.iter().last().is_none() {
+        return;
+    }
+
+    let recorder = match recorder {
+        Some(recorder) => recorder,
+        None => return,
+    };
+
+    if let Some(path) = &recorder.path {
+        match recorder.replay(&seed).save(path) {
+            Ok(()) => info!("saved replay to {}", path.display()),
+            Err(err) => error!("could not save replay to {}: {}", path.display(), err),
+        }
+    }
+}
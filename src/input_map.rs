@@ -0,0 +1,242 @@
+use std::marker::PhantomData;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::save::SaveStorage;
+
+/// Something the player can do, bound to keys and buttons by the `InputMap`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Fire,
+    Pause,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Button(GamepadButtonType),
+    /// Held while the axis is past the threshold, negative thresholds trigger below it
+    Axis(GamepadAxisType, f32),
+}
+
+/// Which keys, buttons and sticks trigger each `Action`, kept in `SaveStorage`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputMap {
+    pub bindings: Vec<(Action, Binding)>,
+    /// Horizontal and vertical axes of the stick used to aim
+    pub aim: (GamepadAxisType, GamepadAxisType),
+    /// Stick movement below this is ignored
+    pub dead_zone: f32,
+    /// Distance from the player that is aimed at with the stick
+    pub aim_distance: f32,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Action::*;
+        use Binding::*;
+
+        Self {
+            bindings: vec![
+                (MoveLeft, Key(KeyCode::A)),
+                (MoveLeft, Key(KeyCode::Left)),
+                (MoveLeft, Button(GamepadButtonType::DPadLeft)),
+                (MoveLeft, Axis(GamepadAxisType::LeftStickX, -0.3)),
+                (MoveRight, Key(KeyCode::D)),
+                (MoveRight, Key(KeyCode::Right)),
+                (MoveRight, Button(GamepadButtonType::DPadRight)),
+                (MoveRight, Axis(GamepadAxisType::LeftStickX, 0.3)),
+                (Jump, Key(KeyCode::W)),
+                (Jump, Key(KeyCode::Space)),
+                (Jump, Button(GamepadButtonType::South)),
+                (Fire, Mouse(MouseButton::Left)),
+                (Fire, Button(GamepadButtonType::RightTrigger2)),
+                (Pause, Key(KeyCode::Escape)),
+                (Pause, Button(GamepadButtonType::Start)),
+            ],
+            aim: (GamepadAxisType::RightStickX, GamepadAxisType::RightStickY),
+            dead_zone: 0.2,
+            aim_distance: 1.0,
+        }
+    }
+}
+
+impl InputMap {
+    /// Name the bindings are kept under in `SaveStorage`
+    pub const NAME: &'static str = "input";
+
+    /// Loads the saved bindings, the defaults if there are none or they can't be read
+    pub fn load(storage: &SaveStorage) -> Self {
+        let text = match storage.read(Self::NAME) {
+            Ok(Some(text)) => text,
+            Ok(None) => return Self::default(),
+            Err(err) => {
+                warn!("could not read input bindings: {}", err);
+                return Self::default();
+            }
+        };
+
+        match ron::de::from_str(&text) {
+            Ok(map) => map,
+            Err(err) => {
+                error!("input bindings can't be read, using the defaults: {}", err);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, storage: &mut SaveStorage) -> Result<(), anyhow::Error> {
+        let text = ron::ser::to_string_pretty(self, Default::default())?;
+        storage.write(Self::NAME, text)
+    }
+
+    /// Adds a binding, keeping the ones already on the action
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        if !self.bindings.contains(&(action, binding)) {
+            self.bindings.push((action, binding));
+        }
+    }
+
+    pub fn unbind(&mut self, action: Action) {
+        self.bindings.retain(|(bound, _)| *bound != action);
+    }
+
+    fn bindings(&self, action: Action) -> impl Iterator<Item = &Binding> {
+        self.bindings
+            .iter()
+            .filter(move |(bound, _)| *bound == action)
+            .map(|(_, binding)| binding)
+    }
+
+    pub fn pressed(&self, action: Action, devices: &InputDevices) -> bool {
+        self.bindings(action)
+            .any(|binding| devices.pressed(binding))
+    }
+
+    pub fn just_pressed(&self, action: Action, devices: &InputDevices) -> bool {
+        self.bindings(action)
+            .any(|binding| devices.just_pressed(binding))
+    }
+
+    /// Direction the aim stick is pushed in, if it is past the dead zone
+    pub fn aim(&self, devices: &InputDevices) -> Option<Vec2> {
+        let (x, y) = self.aim;
+        let stick = Vec2::new(devices.axis(x), devices.axis(y));
+
+        (stick.length() > self.dead_zone).then(|| stick.normalize())
+    }
+}
+
+/// Every device an `InputMap` binding can come from
+#[derive(SystemParam)]
+pub struct InputDevices<'w, 's> {
+    pub keyboard: Res<'w, Input<KeyCode>>,
+    pub mouse: Res<'w, Input<MouseButton>>,
+    pub gamepads: Res<'w, Gamepads>,
+    pub buttons: Res<'w, Input<GamepadButton>>,
+    pub axes: Res<'w, Axis<GamepadAxis>>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+/// Write access to the devices with buttons, only for systems that reset bindings
+#[derive(SystemParam)]
+pub struct InputDevicesMut<'w, 's> {
+    pub keyboard: ResMut<'w, Input<KeyCode>>,
+    pub mouse: ResMut<'w, Input<MouseButton>>,
+    pub gamepads: Res<'w, Gamepads>,
+    pub buttons: ResMut<'w, Input<GamepadButton>>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> InputDevices<'w, 's> {
+    pub fn pressed(&self, binding: &Binding) -> bool {
+        match *binding {
+            Binding::Key(key) => self.keyboard.pressed(key),
+            Binding::Mouse(button) => self.mouse.pressed(button),
+            Binding::Button(button) => self
+                .gamepads
+                .iter()
+                .any(|gamepad| self.buttons.pressed(GamepadButton(*gamepad, button))),
+            Binding::Axis(axis, threshold) => {
+                let value = self.axis(axis);
+
+                if threshold < 0.0 {
+                    value <= threshold
+                } else {
+                    value >= threshold
+                }
+            }
+        }
+    }
+
+    /// Axes have no notion of being pressed this frame, so they never are
+    pub fn just_pressed(&self, binding: &Binding) -> bool {
+        match *binding {
+            Binding::Key(key) => self.keyboard.just_pressed(key),
+            Binding::Mouse(button) => self.mouse.just_pressed(button),
+            Binding::Button(button) => self
+                .gamepads
+                .iter()
+                .any(|gamepad| self.buttons.just_pressed(GamepadButton(*gamepad, button))),
+            Binding::Axis(..) => false,
+        }
+    }
+
+    /// Position of an axis on the first gamepad that has it off centre
+    pub fn axis(&self, axis: GamepadAxisType) -> f32 {
+        self.gamepads
+            .iter()
+            .filter_map(|gamepad| self.axes.get(GamepadAxis(*gamepad, axis)))
+            .find(|value| *value != 0.0)
+            .unwrap_or(0.0)
+    }
+}
+
+impl<'w, 's> InputDevicesMut<'w, 's> {
+    /// Stops a binding from being read as just pressed again this frame
+    pub fn reset(&mut self, binding: &Binding) {
+        match *binding {
+            Binding::Key(key) => self.keyboard.reset(key),
+            Binding::Mouse(button) => self.mouse.reset(button),
+            Binding::Button(button) => {
+                for gamepad in self.gamepads.iter() {
+                    self.buttons.reset(GamepadButton(*gamepad, button));
+                }
+            }
+            Binding::Axis(..) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_map_round_trips() {
+        let map = InputMap::default();
+        let text = ron::ser::to_string_pretty(&map, Default::default()).unwrap();
+
+        assert_eq!(ron::de::from_str::<InputMap>(&text).unwrap(), map);
+    }
+
+    #[test]
+    fn bindings_are_kept_in_save_storage() {
+        let mut storage = SaveStorage::Memory(default());
+        assert_eq!(InputMap::load(&storage), InputMap::default());
+
+        let mut map = InputMap::default();
+        map.unbind(Action::Fire);
+        map.bind(Action::Fire, Binding::Key(KeyCode::F));
+        map.save(&mut storage).unwrap();
+
+        assert_eq!(InputMap::load(&storage), map);
+    }
+}
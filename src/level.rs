@@ -0,0 +1,48 @@
+use bevy::prelude::*;
+
+use crate::{level_file::CurrentLevel, terrain::TerrainSeed};
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RestartLevelEvent>()
+            .add_event::<NextLevelEvent>()
+            .init_resource::<LevelNumber>()
+            .add_system(next_level);
+    }
+}
+
+/// Tears the current level down and spawns it again from the `TerrainSeed`.
+///
+/// Every plugin that spawns part of the level despawns and respawns its own
+/// entities when this is sent.
+pub struct RestartLevelEvent;
+
+/// Moves on to a new, harder generated level once the current one is finished
+pub struct NextLevelEvent;
+
+/// How many levels have been finished before this one, later levels are harder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LevelNumber(pub u32);
+
+fn next_level(
+    mut events: EventReader<NextLevelEvent>,
+    mut number: ResMut<LevelNumber>,
+    mut seed: ResMut<TerrainSeed>,
+    mut level: ResMut<CurrentLevel>,
+    mut restart: EventWriter<RestartLevelEvent>,
+) {
+    if events.iter().last().is_none() {
+        return;
+    }
+
+    number.0 += 1;
+    seed.0 = seed.0.wrapping_add(1);
+
+    // Hand-made levels lead on to generated ones
+    level.0 = None;
+
+    info!("level {}, seed {}", number.0 + 1, seed.0);
+    restart.send(RestartLevelEvent);
+}
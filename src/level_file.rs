@@ -0,0 +1,174 @@
+use std::{fs, path::Path};
+
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block_type::{BlockType, BLOCK_SIZE},
+    config::GameConfig,
+    level::RestartLevelEvent,
+    terrain::surface_heights,
+    traversal::{self, Reach},
+    utils::arg_value,
+};
+
+/// A hand-made level, loaded from a `.level.ron` file instead of being generated.
+///
+/// Positions are block indices, things are placed on the surface of their block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TypeUuid)]
+#[uuid = "0b4cbf5e-8f57-4d0e-9f0c-3f6f3c1e7a52"]
+pub struct LevelFile {
+    pub name: String,
+    /// Block the player starts on
+    pub spawn: u32,
+    /// Block the goal is on
+    pub goal: u32,
+    pub blocks: Vec<BlockType>,
+    /// Blocks with a pepper above them
+    #[serde(default)]
+    pub peppers: Vec<u32>,
+    #[serde(default)]
+    pub enemies: Vec<EnemySpawn>,
+    /// Blocks with a checkpoint post
+    #[serde(default)]
+    pub checkpoints: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnemySpawn {
+    pub block: u32,
+    /// Name of a kind in `enemies.kinds.ron`
+    pub kind: String,
+}
+
+impl LevelFile {
+    pub const EXTENSION: &'static str = "level.ron";
+
+    /// Blocks with how much each one rises, the same as generated terrain
+    pub fn block_rises(&self) -> Vec<(BlockType, f32)> {
+        self.blocks
+            .iter()
+            .map(|block| (*block, block.rise()))
+            .collect()
+    }
+
+    /// Centre of a block's surface
+    pub fn surface(&self, block: u32) -> Vec2 {
+        let blocks = self.block_rises();
+        let heights = surface_heights(0.0, &blocks);
+        let block = (block as usize).min(blocks.len().saturating_sub(1));
+        let rise = blocks.get(block).map_or(0.0, |(_, rise)| *rise);
+
+        Vec2::new(block as f32 * BLOCK_SIZE, heights[block] + rise / 2.0)
+    }
+
+    /// Block under a world x position, clamped to the level
+    pub fn block_at(&self, x: f32) -> u32 {
+        let block = (x / BLOCK_SIZE).round().max(0.0) as u32;
+        block.min(self.blocks.len().saturating_sub(1) as u32)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let text = ron::ser::to_string_pretty(self, Default::default())?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+/// The hand-made level being played, `None` while playing generated terrain
+#[derive(Default)]
+pub struct CurrentLevel(pub Option<LevelFile>);
+
+/// Level file picked with `--level <path>`, kept loaded so it can be hot-reloaded
+pub struct LevelFileHandle(pub Handle<LevelFile>);
+
+#[derive(Default)]
+pub struct LevelFileLoader;
+
+impl AssetLoader for LevelFileLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let level = ron::de::from_bytes::<LevelFile>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[LevelFile::EXTENSION]
+    }
+}
+
+pub(crate) fn load_level_file(mut commands: Commands, server: Res<AssetServer>) {
+    if let Some(path) = arg_value("--level") {
+        info!("loading level {}", path);
+        commands.insert_resource(LevelFileHandle(server.load(&path)));
+    }
+}
+
+/// Switches to the level file once it has loaded and whenever it changes
+pub(crate) fn apply_level_file(
+    mut events: EventReader<AssetEvent<LevelFile>>,
+    handle: Option<Res<LevelFileHandle>>,
+    assets: Res<Assets<LevelFile>>,
+    config: Res<GameConfig>,
+    mut current: ResMut<CurrentLevel>,
+    mut restart: EventWriter<RestartLevelEvent>,
+) {
+    let handle = match handle {
+        Some(handle) => handle,
+        None => return,
+    };
+
+    for ev in events.iter() {
+        match ev {
+            AssetEvent::Created { handle: changed } | AssetEvent::Modified { handle: changed } => {
+                if *changed != handle.0 {
+                    continue;
+                }
+
+                if let Some(level) = assets.get(changed) {
+                    // Levels that can't be finished are never played, the last good one is kept
+                    let problems = traversal::validate(&level.block_rises(), &Reach::new(&config));
+                    if !problems.is_empty() {
+                        for problem in problems {
+                            error!("{} can't be finished: {}", level.name, problem);
+                        }
+                        continue;
+                    }
+
+                    info!("Loaded level {}", level.name);
+                    current.0 = Some(level.clone());
+                    restart.send(RestartLevelEvent);
+                }
+            }
+            AssetEvent::Removed { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tutorial_parses() {
+        let level: LevelFile =
+            ron::de::from_str(include_str!("../assets/levels/tutorial.level.ron")).unwrap();
+
+        assert!(level.spawn < level.goal);
+        assert!((level.goal as usize) < level.blocks.len());
+        assert!(
+            traversal::validate(&level.block_rises(), &Reach::new(&GameConfig::default()))
+                .is_empty()
+        );
+    }
+}
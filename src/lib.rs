@@ -18,16 +18,36 @@ mod polyfill {
 
 #[cfg(debug_assertions)]
 pub mod debug;
+#[cfg(debug_assertions)]
+pub mod editor;
 
 pub mod assets;
 pub mod block_type;
 pub mod camera;
+pub mod checkpoint;
 pub mod collisions;
+pub mod config;
+pub mod controller;
 pub mod enemy;
+pub mod enemy_kind;
 pub mod fireball;
+pub mod goal;
+pub mod ground;
+pub mod headless;
 pub mod health;
 pub mod hud;
+pub mod input;
+pub mod input_map;
+pub mod level;
+pub mod level_file;
+pub mod menu;
 pub mod pepper;
 pub mod player;
+pub mod plugins;
+pub mod save;
+pub mod snapshot;
+pub mod state;
+pub mod stats;
 pub mod terrain;
+pub mod traversal;
 pub mod utils;
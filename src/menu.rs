@@ -0,0 +1,310 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    assets::GameAssets,
+    level::{NextLevelEvent, RestartLevelEvent},
+    level_file::CurrentLevel,
+    save::{RecordRun, SaveData, SaveStorage},
+    snapshot::{LoadSnapshotEvent, SaveSnapshotEvent, Snapshot},
+    state::AppState,
+    stats::RunStats,
+    terrain::TerrainSeed,
+};
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(main_menu))
+            .add_system_set(SystemSet::on_exit(AppState::MainMenu).with_system(despawn_menu))
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(pause_menu))
+            .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(despawn_menu))
+            .add_system_set(SystemSet::on_enter(AppState::GameOver).with_system(game_over_menu))
+            .add_system_set(SystemSet::on_exit(AppState::GameOver).with_system(despawn_menu))
+            .add_system_set(
+                SystemSet::on_enter(AppState::Victory).with_system(victory_menu.after(RecordRun)),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Victory).with_system(despawn_menu))
+            .add_system(menu_buttons)
+            .add_system(highlight_buttons);
+    }
+}
+
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+
+/// Root node of whichever menu is on screen
+#[derive(Component)]
+struct MenuScreen;
+
+#[derive(Component, Clone, Copy)]
+enum MenuButton {
+    Play,
+    Continue,
+    Resume,
+    Restart,
+    NextLevel,
+    Quit,
+}
+
+impl MenuButton {
+    fn label(&self) -> &'static str {
+        match self {
+            MenuButton::Play => "Play",
+            MenuButton::Continue => "Continue",
+            MenuButton::Resume => "Resume",
+            MenuButton::Restart => "Restart",
+            MenuButton::NextLevel => "Next Level",
+            MenuButton::Quit => "Quit",
+        }
+    }
+}
+
+fn main_menu(commands: Commands, assets: Res<GameAssets>, storage: Res<SaveStorage>) {
+    // Runs quit part way through can be carried on with
+    let buttons = match storage.read(Snapshot::NAME) {
+        Ok(Some(_)) => vec![MenuButton::Continue, MenuButton::Play, MenuButton::Quit],
+        _ => vec![MenuButton::Play, MenuButton::Quit],
+    };
+
+    spawn_menu(commands, &assets, "Kajam", &[], &buttons);
+}
+
+fn pause_menu(commands: Commands, assets: Res<GameAssets>) {
+    spawn_menu(
+        commands,
+        &assets,
+        "Paused",
+        &[],
+        &[MenuButton::Resume, MenuButton::Restart, MenuButton::Quit],
+    );
+}
+
+fn game_over_menu(commands: Commands, assets: Res<GameAssets>) {
+    spawn_menu(
+        commands,
+        &assets,
+        "Game Over",
+        &[],
+        &[MenuButton::Restart, MenuButton::Quit],
+    );
+}
+
+fn victory_menu(
+    commands: Commands,
+    assets: Res<GameAssets>,
+    stats: Res<RunStats>,
+    save: Res<SaveData>,
+    seed: Res<TerrainSeed>,
+    level: Res<CurrentLevel>,
+) {
+    let mut lines = vec![
+        format!("Score: {}", stats.score()),
+        format!("Time: {}", format_time(stats.time)),
+        format!("Distance: {:.0}", stats.distance),
+        format!("Peppers: {}", stats.peppers),
+        format!(
+            "Fireballs: {} fired, {:.0}% hit",
+            stats.fireballs_fired,
+            stats.hit_rate() * 100.0
+        ),
+        format!("Enemies killed: {}", stats.enemies_killed),
+        format!(
+            "Damage: {:.0} dealt, {:.0} taken",
+            stats.damage_dealt, stats.damage_taken
+        ),
+    ];
+
+    // Only generated levels keep records, by seed
+    let record = match level.0 {
+        Some(_) => None,
+        None => save.seeds.get(&seed.0),
+    };
+
+    if let Some(record) = record {
+        let scores: Vec<_> = record.high_scores.iter().map(u32::to_string).collect();
+        lines.push(format!("High scores: {}", scores.join(", ")));
+
+        if let Some(best) = record.best_time {
+            lines.push(format!("Best time: {}", format_time(best)));
+        }
+    }
+
+    spawn_menu(
+        commands,
+        &assets,
+        "Level Complete!",
+        &lines,
+        &[MenuButton::NextLevel, MenuButton::Restart, MenuButton::Quit],
+    );
+}
+
+/// Minutes and seconds, e.g. `1:05.3`
+fn format_time(seconds: f32) -> String {
+    let minutes = (seconds / 60.0).floor();
+    format!("{}:{:04.1}", minutes, seconds - minutes * 60.0)
+}
+
+/// Spawns a title, a line of text for each of `lines` and the buttons
+fn spawn_menu(
+    mut commands: Commands,
+    assets: &GameAssets,
+    title: &str,
+    lines: &[String],
+    buttons: &[MenuButton],
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..default()
+        })
+        .insert(Name::new("Menu"))
+        .insert(MenuScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(20.0)),
+                    ..default()
+                },
+                text: Text::with_section(
+                    title,
+                    TextStyle {
+                        font: assets.fonts.medium.clone_weak(),
+                        font_size: 60.0,
+                        color: Color::WHITE,
+                    },
+                    default(),
+                ),
+                ..default()
+            });
+
+            for line in lines {
+                parent.spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(5.0)),
+                        ..default()
+                    },
+                    text: Text::with_section(
+                        line,
+                        TextStyle {
+                            font: assets.fonts.regular.clone_weak(),
+                            font_size: 25.0,
+                            color: Color::WHITE,
+                        },
+                        default(),
+                    ),
+                    ..default()
+                });
+            }
+
+            for button in buttons {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(200.0), Val::Px(50.0)),
+                            margin: Rect::all(Val::Px(10.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        color: BUTTON_COLOR.into(),
+                        ..default()
+                    })
+                    .insert(*button)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                button.label(),
+                                TextStyle {
+                                    font: assets.fonts.regular.clone_weak(),
+                                    font_size: 25.0,
+                                    color: Color::WHITE,
+                                },
+                                default(),
+                            ),
+                            ..default()
+                        });
+                    });
+            }
+        });
+}
+
+fn despawn_menu(mut commands: Commands, query: Query<Entity, With<MenuScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn menu_buttons(
+    query: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    mut state: ResMut<State<AppState>>,
+    mut restart: EventWriter<RestartLevelEvent>,
+    mut next: EventWriter<NextLevelEvent>,
+    mut load: EventWriter<LoadSnapshotEvent>,
+    mut save: EventWriter<SaveSnapshotEvent>,
+    mut exit: EventWriter<AppExit>,
+    storage: Res<SaveStorage>,
+) {
+    for (interaction, button) in query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        let result = match button {
+            MenuButton::Play => state.set(AppState::Playing),
+            MenuButton::Continue => {
+                if let Some(snapshot) = Snapshot::load(&storage) {
+                    load.send(LoadSnapshotEvent(snapshot));
+                }
+
+                state.set(AppState::Playing)
+            }
+            MenuButton::Resume => state.pop(),
+            MenuButton::Restart => {
+                restart.send(RestartLevelEvent);
+
+                // Paused sits on top of Playing, so pop back down instead of stacking another
+                match state.current() {
+                    AppState::Paused => state.pop(),
+                    _ => state.set(AppState::Playing),
+                }
+            }
+            MenuButton::NextLevel => {
+                next.send(NextLevelEvent);
+                state.set(AppState::Playing)
+            }
+            MenuButton::Quit => {
+                // Quitting part way through a level keeps the run to continue later
+                if *state.current() == AppState::Paused {
+                    save.send(SaveSnapshotEvent);
+                }
+
+                exit.send(AppExit);
+                Ok(())
+            }
+        };
+
+        if let Err(err) = result {
+            warn!("could not change state: {:?}", err);
+        }
+    }
+}
+
+fn highlight_buttons(
+    mut query: Query<(&Interaction, &mut UiColor), (Changed<Interaction>, With<MenuButton>)>,
+) {
+    for (interaction, mut color) in query.iter_mut() {
+        *color = match interaction {
+            Interaction::Hovered | Interaction::Clicked => HOVERED_BUTTON_COLOR.into(),
+            Interaction::None => BUTTON_COLOR.into(),
+        };
+    }
+}
@@ -1,77 +1,168 @@
 use bevy::prelude::*;
 use heron::{CollisionLayers, CollisionShape, RigidBody, RotationConstraints, Velocity};
-use rand::Rng;
+use rand::{rngs::StdRng, Rng};
 
 use crate::{
-    assets::GameAssets, block_type::BLOCK_SIZE, player::PLAYER_SIZE, terrain::MAP_LEN,
+    assets::GameAssets,
+    block_type::BlockType,
+    input::InputFrame,
+    level_file::CurrentLevel,
+    player::PLAYER_SIZE,
+    state::AppState,
+    terrain::{ChunkMember, ChunkSpawnedEvent, Terrain, TerrainSeed},
     utils::Layers,
 };
 
+/// Keeps pepper placement independent from the other seeded rngs
+pub(crate) const PEPPER_SALT: u64 = 0x0070_6570_7065_7273;
+/// Blocks per pepper
+const PEPPER_SPACING: usize = 10;
+
 pub struct PepperPlugin;
 
 impl Plugin for PepperPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_peppers)
-            .add_system(hover_effect);
+        app.add_event::<PepperCollectedEvent>()
+            .add_system(spawn_peppers)
+            .add_system(record_collected)
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(hover_effect));
     }
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy)]
 pub struct Pepper {
-    id: f32,
+    /// Offsets the hover so peppers don't all bob together
+    pub id: f32,
+    /// Block it was placed above
+    pub block: u32,
 }
 
-fn spawn_peppers(mut commands: Commands, assets: Res<GameAssets>) {
-    let mut rng = rand::thread_rng();
-    let pepper_count = MAP_LEN / 10_u32;
-    let range = (
-        Vec2::splat(BLOCK_SIZE * 5.0),
-        Vec2::splat((MAP_LEN as f32 * BLOCK_SIZE) - (BLOCK_SIZE * 5.0)),
-    );
-
-    for i in 0..pepper_count {
-        let noise = rng.gen::<f32>() * 10.0;
-        let x = range.0.lerp(range.1, i as f32 / pepper_count as f32).x + (noise * BLOCK_SIZE);
-
-        let loc = Vec3::new(x, 0.75, 800.0);
-
-        let bundle = SpriteBundle {
-            sprite: Sprite {
-                custom_size: Some(Vec2::splat(PLAYER_SIZE * 0.8)),
-                ..Default::default()
-            },
-            texture: assets.pepper.clone_weak(),
-            transform: Transform {
-                translation: loc,
-                ..Default::default()
-            },
-            ..Default::default()
+/// Sent when the player picks up a pepper
+pub struct PepperCollectedEvent {
+    pub chunk: u32,
+    pub block: u32,
+}
+
+fn spawn_peppers(
+    mut commands: Commands,
+    assets: Option<Res<GameAssets>>,
+    seed: Res<TerrainSeed>,
+    level: Res<CurrentLevel>,
+    terrain: Res<Terrain>,
+    mut events: EventReader<ChunkSpawnedEvent>,
+) {
+    for ev in events.iter() {
+        if ev.restored {
+            continue;
+        }
+
+        let mut rng = seed.rng_at(PEPPER_SALT, ev.chunk);
+
+        let blocks = match &level.0 {
+            Some(file) => file.peppers.clone(),
+            None => random_blocks(&mut rng, &ev.blocks, ev.first_block),
         };
 
-        commands
-            .spawn_bundle(bundle)
-            .insert(Name::new("pepper"))
-            .insert(RigidBody::Dynamic)
-            .insert(Velocity::default())
-            .insert(CollisionShape::Cuboid {
-                half_extends: Vec3::splat(PLAYER_SIZE * 0.8),
-                border_radius: None,
-            })
-            .insert(
-                CollisionLayers::none()
-                    .with_group(Layers::Pepper)
-                    .with_masks(&[Layers::Player, Layers::Level]),
-            )
-            .insert(RotationConstraints::lock())
-            .insert(Pepper {
-                id: rng.gen::<f32>() * 1.5,
-            });
+        for block in blocks {
+            let i = match block.checked_sub(ev.first_block) {
+                Some(i) if (i as usize) < ev.surface.len() => i as usize,
+                _ => continue,
+            };
+
+            // Drawn even for collected peppers so the others keep their hover
+            let id = rng.gen::<f32>() * 1.5;
+
+            if terrain.pepper_collected(ev.chunk, block) {
+                continue;
+            }
+
+            let translation = (ev.surface[i] + Vec2::Y * 0.3).extend(800.0);
+            let pepper = Pepper { id, block };
+            spawn_pepper(
+                &mut commands,
+                assets.as_deref(),
+                ev.chunk,
+                translation,
+                pepper,
+            );
+        }
+    }
+}
+
+/// Picks a block with ground somewhere in every `PEPPER_SPACING` of `blocks`
+pub(crate) fn random_blocks(rng: &mut StdRng, blocks: &[BlockType], first_block: u32) -> Vec<u32> {
+    let mut picked = Vec::new();
+
+    for (i, group) in blocks.chunks(PEPPER_SPACING).enumerate() {
+        let offset = rng.gen_range(0..group.len());
+
+        // Peppers fall, so one over a gap moves to the start of its group
+        let offset = if group[offset].is_solid() { offset } else { 0 };
+        picked.push(first_block + (i * PEPPER_SPACING + offset) as u32);
+    }
+
+    picked
+}
+
+/// `assets` is `None` when running headless
+pub(crate) fn spawn_pepper(
+    commands: &mut Commands,
+    assets: Option<&GameAssets>,
+    chunk: u32,
+    translation: Vec3,
+    pepper: Pepper,
+) -> Entity {
+    let bundle = SpriteBundle {
+        sprite: Sprite {
+            custom_size: Some(Vec2::splat(PLAYER_SIZE * 0.8)),
+            ..Default::default()
+        },
+        texture: assets
+            .map(|assets| assets.pepper.clone_weak())
+            .unwrap_or_default(),
+        transform: Transform {
+            translation,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    commands
+        .spawn_bundle(bundle)
+        .insert(Name::new("pepper"))
+        .insert(RigidBody::Dynamic)
+        .insert(Velocity::default())
+        .insert(CollisionShape::Cuboid {
+            half_extends: Vec3::splat(PLAYER_SIZE * 0.8),
+            border_radius: None,
+        })
+        .insert(
+            CollisionLayers::none()
+                .with_group(Layers::Pepper)
+                .with_masks(&[Layers::Player, Layers::Level]),
+        )
+        .insert(RotationConstraints::lock())
+        .insert(ChunkMember(chunk))
+        .insert(pepper)
+        .id()
+}
+
+/// Keeps collected peppers from coming back when their chunk streams in again
+fn record_collected(mut terrain: ResMut<Terrain>, mut events: EventReader<PepperCollectedEvent>) {
+    for ev in events.iter() {
+        terrain.record(ev.chunk).peppers.insert(ev.block);
     }
 }
 
-fn hover_effect(mut query: Query<(&mut Transform, &Pepper)>, time: Res<Time>) {
-    let elapsed = time.seconds_since_startup() as f32;
-    let delta = time.delta_seconds();
+fn hover_effect(
+    mut query: Query<(&mut Transform, &Pepper)>,
+    input: Res<InputFrame>,
+    mut elapsed: Local<f32>,
+) {
+    // Driven by the input clock so replays move peppers the same way
+    let delta = input.delta;
+    *elapsed += delta;
+    let elapsed = *elapsed;
 
     for (mut transform, pepper) in query.iter_mut() {
         transform.translation.y +=
@@ -1,38 +1,87 @@
-use crate::{block_type::BLOCK_SIZE, camera::MainCamera, health::Health, utils::Layers};
+use crate::{
+    block_type::BLOCK_SIZE,
+    camera::MainCamera,
+    checkpoint::Checkpoint,
+    config::GameConfig,
+    controller::{
+        CharacterController, ControllerBundle, ControllerInput, ControllerState, ControllerStep,
+    },
+    ground::Grounded,
+    health::{
+        ApplyDamage, DamageEvent, DamageKind, DamagedEvent, DiedEvent, Health, Invulnerability,
+    },
+    hud::{UpdateHealthPointsEvent, UpdateLivesEvent, UpdatePepperCountEvent},
+    input::InputFrame,
+    level::RestartLevelEvent,
+    level_file::CurrentLevel,
+    state::AppState,
+    terrain::FellInPitEvent,
+    utils::Layers,
+};
 
 use bevy::prelude::*;
 use bevy_inspector_egui::Inspectable;
 use heron::prelude::*;
 
 pub const PLAYER_SIZE: f32 = BLOCK_SIZE / 2.0;
+/// Where the player starts generated levels, hand-made ones have their own spawn block
 const SPAWN_POINT: [f32; 3] = [0.0, 0.75, 999.0];
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_startup_system(spawn_player)
-            .add_system(player_movement)
+        app.add_event::<FellOutEvent>()
+            .add_startup_system(spawn_player)
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(player_movement.before(ControllerStep))
+                    .with_system(reset_player.before(ApplyDamage))
+                    .with_system(protect_after_fall.after(ApplyDamage))
+                    .with_system(player_died)
+                    .with_system(knockback)
+                    .with_system(flash_when_invulnerable),
+            )
+            .add_system(apply_player_config)
             .add_system(camera_follow)
-            .add_system(reset_player);
+            .add_system(report_player_health)
+            .add_system(report_lives)
+            .add_system(restart_player);
     }
 }
 
-#[derive(Component)]
-pub struct Limits {
-    max_velocity: Vec2,
-}
-
 #[derive(Component, Inspectable)]
 pub struct Player {
-    pub speed: f32,
-    pub jump_height: f32,
-    pub jumped: bool,
     pub peppers: u32,
 }
 
-fn spawn_player(mut commands: Commands) {
-    let geometry = load_player_sprite();
+/// Deaths left before the game is over, refilled when the level restarts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lives(pub u32);
+
+/// Where the player starts the current level
+fn spawn_point(level: &CurrentLevel) -> Vec3 {
+    match &level.0 {
+        Some(file) => {
+            let ground = file.surface(file.spawn);
+            Vec3::new(ground.x, ground.y + SPAWN_POINT[1], SPAWN_POINT[2])
+        }
+        None => SPAWN_POINT.into(),
+    }
+}
+
+/// Where the player comes back after falling or dying
+fn respawn_point(level: &CurrentLevel, checkpoint: &Checkpoint) -> Vec3 {
+    match &checkpoint.0 {
+        Some(post) => post.respawn,
+        None => spawn_point(level),
+    }
+}
+
+fn spawn_player(mut commands: Commands, config: Res<GameConfig>, level: Res<CurrentLevel>) {
+    let geometry = load_player_sprite(spawn_point(&level));
+
+    commands.insert_resource(Lives(config.player.lives));
 
     commands
         .spawn_bundle(geometry)
@@ -44,7 +93,7 @@ fn spawn_player(mut commands: Commands) {
             radius: PLAYER_SIZE,
         })
         .insert(PhysicMaterial {
-            restitution: 0.35,
+            restitution: 0.0,
             friction: 0.0, //PLAYER_SIZE / 10.0,
             density: PLAYER_SIZE,
         })
@@ -52,21 +101,23 @@ fn spawn_player(mut commands: Commands) {
         .insert(
             CollisionLayers::none()
                 .with_group(Layers::Player)
-                .with_masks(&[Layers::Enemy, Layers::Level, Layers::Pepper]),
+                .with_masks(&[
+                    Layers::Enemy,
+                    Layers::Level,
+                    Layers::Pepper,
+                    Layers::EnemyFireball,
+                    Layers::Platform,
+                    Layers::Trigger,
+                ]),
         )
-        .insert(Player {
-            speed: 100.0,
-            jump_height: 1500.0,
-            jumped: false,
-            peppers: 0,
-        })
-        .insert(Limits {
-            max_velocity: Vec2::new(2000.0, 3000.0),
-        })
-        .insert(Health(500.0));
+        .insert(Player { peppers: 0 })
+        .insert_bundle(ControllerBundle::new(config.player.controller.clone()))
+        .insert(Grounded::new(PLAYER_SIZE * 1.5))
+        .insert(Health(config.player.health))
+        .insert(Invulnerability::new(config.player.invulnerability));
 }
 
-fn load_player_sprite() -> impl Bundle {
+fn load_player_sprite(spawn: Vec3) -> impl Bundle {
     let sprite = Sprite {
         color: Color::Rgba {
             red: 1.0,
@@ -80,15 +131,35 @@ fn load_player_sprite() -> impl Bundle {
 
     return SpriteBundle {
         sprite,
-        transform: Transform::default().with_translation(SPAWN_POINT.into()),
+        transform: Transform::default().with_translation(spawn),
         ..Default::default()
     };
 }
 
+fn apply_player_config(
+    config: Res<GameConfig>,
+    mut player_query: Query<(&mut CharacterController, &mut Invulnerability), With<Player>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+
+    for (mut controller, mut invulnerability) in player_query.iter_mut() {
+        *controller = config.player.controller.clone();
+        invulnerability.duration = config.player.invulnerability;
+    }
+}
+
 fn camera_follow(
     player_query: Query<&Transform, With<Player>>,
     mut camera_query: Query<&mut Transform, (Without<Player>, With<MainCamera>)>,
+    state: Res<State<AppState>>,
 ) {
+    // The editor moves the camera itself
+    if *state.current() == AppState::Editing {
+        return;
+    }
+
     let player_transform = player_query.single();
     let mut camera_transform = camera_query.single_mut();
 
@@ -97,44 +168,222 @@ fn camera_follow(
 }
 
 fn player_movement(
-    mut player_query: Query<(&mut Player, &Limits, &mut Velocity)>,
-    keyboard: Res<Input<KeyCode>>,
-    time: Res<Time>,
+    mut player_query: Query<&mut ControllerInput, With<Player>>,
+    input: Res<InputFrame>,
 ) {
-    let delta = time.delta_seconds();
-    let (player, limits, mut velocity) = player_query.single_mut();
+    let mut controller = player_query.single_mut();
 
-    let max_y = limits.max_velocity.y * PLAYER_SIZE * delta;
-    let max_x = limits.max_velocity.x * PLAYER_SIZE * delta;
+    controller.movement = match (input.left, input.right) {
+        (true, false) => -1.0,
+        (false, true) => 1.0,
+        _ => 0.0,
+    };
+    controller.jump = input.jump;
+}
 
-    velocity.linear.y += PLAYER_SIZE * delta;
+/// Sent when the player is put back at the last checkpoint after falling out of the level
+struct FellOutEvent(Entity);
 
-    if !player.jumped && velocity.linear.y.abs() <= max_y {
-        if keyboard.pressed(KeyCode::W) || keyboard.pressed(KeyCode::Space) {
-            velocity.linear.y += player.jump_height * PLAYER_SIZE * delta;
+/// Puts the player back at the last checkpoint when they fall out of the level,
+/// which costs some health
+fn reset_player(
+    mut player_query: Query<
+        (
+            Entity,
+            &Health,
+            &mut ControllerState,
+            &mut Transform,
+            &mut Velocity,
+        ),
+        With<Player>,
+    >,
+    mut events: EventReader<FellInPitEvent>,
+    mut damage: EventWriter<DamageEvent>,
+    mut fell_out: EventWriter<FellOutEvent>,
+    level: Res<CurrentLevel>,
+    checkpoint: Res<Checkpoint>,
+    config: Res<GameConfig>,
+) {
+    let (entity, health, mut state, mut transform, mut velocity) = player_query.single_mut();
+    let spawn = spawn_point(&level);
+    let fell = events.iter().any(|ev| ev.0 == entity);
+
+    // Already out of health, `player_died` takes it from here
+    if health.0 <= 0.0 || !(fell || transform.translation.y < spawn.y - 2.25) {
+        return;
+    }
+
+    // A fall that takes the last of the player's health is a death like any other
+    damage.send(DamageEvent {
+        target: entity,
+        amount: config.player.fall_damage,
+        source: None,
+        kind: DamageKind::Hazard,
+    });
+
+    respawn(
+        &mut state,
+        &mut transform,
+        &mut velocity,
+        respawn_point(&level, &checkpoint),
+    );
+    fell_out.send(FellOutEvent(entity));
+}
+
+/// Grants the respawn invulnerability once the fall damage has been taken,
+/// granting it any earlier would block the fall damage too
+fn protect_after_fall(
+    mut query: Query<&mut Invulnerability>,
+    mut events: EventReader<FellOutEvent>,
+    config: Res<GameConfig>,
+) {
+    for ev in events.iter() {
+        if let Ok(mut invulnerability) = query.get_mut(ev.0) {
+            invulnerability.grant(config.player.respawn_invulnerability);
         }
     }
+}
+
+fn respawn(
+    state: &mut ControllerState,
+    transform: &mut Transform,
+    velocity: &mut Velocity,
+    point: Vec3,
+) {
+    *state = ControllerState::default();
+    transform.translation = point;
+    *velocity = Velocity::from_linear(Vec3::X * 0.0);
+}
+
+/// Puts the player back at the start of the level with full health
+fn restart_player(
+    mut player_query: Query<(
+        &mut Player,
+        &mut Health,
+        &mut Invulnerability,
+        &mut ControllerState,
+        &mut Transform,
+        &mut Velocity,
+    )>,
+    mut events: EventReader<RestartLevelEvent>,
+    config: Res<GameConfig>,
+    level: Res<CurrentLevel>,
+    mut pepper_event: EventWriter<UpdatePepperCountEvent>,
+    mut lives: ResMut<Lives>,
+) {
+    if events.iter().last().is_none() {
+        return;
+    }
+
+    lives.0 = config.player.lives;
+
+    let (mut player, mut health, mut invulnerability, mut state, mut transform, mut velocity) =
+        player_query.single_mut();
+
+    *state = ControllerState::default();
+    player.peppers = 0;
+    health.0 = config.player.health;
+    *invulnerability = Invulnerability::new(config.player.invulnerability);
+    transform.translation = spawn_point(&level);
+    *velocity = Velocity::from_linear(Vec3::X * 0.0);
+
+    pepper_event.send(UpdatePepperCountEvent(player.peppers));
+}
+
+fn report_player_health(
+    query: Query<&Health, (With<Player>, Changed<Health>)>,
+    mut events: EventWriter<UpdateHealthPointsEvent>,
+) {
+    for health in query.iter() {
+        events.send(UpdateHealthPointsEvent(health.0.max(0.0)));
+    }
+}
 
-    if (velocity.linear.x >= -max_x)
-        && (keyboard.pressed(KeyCode::A) || keyboard.pressed(KeyCode::Left))
-    {
-        velocity.linear.x += -player.speed * PLAYER_SIZE * delta;
+fn report_lives(lives: Res<Lives>, mut events: EventWriter<UpdateLivesEvent>) {
+    if lives.is_changed() {
+        events.send(UpdateLivesEvent(lives.0));
     }
+}
+
+/// Takes a life and respawns the player at the last checkpoint, ending the
+/// game once there are none left
+fn player_died(
+    mut query: Query<
+        (
+            &mut Health,
+            &mut Invulnerability,
+            &mut ControllerState,
+            &mut Transform,
+            &mut Velocity,
+        ),
+        With<Player>,
+    >,
+    mut events: EventReader<DiedEvent>,
+    mut lives: ResMut<Lives>,
+    mut state: ResMut<State<AppState>>,
+    level: Res<CurrentLevel>,
+    checkpoint: Res<Checkpoint>,
+    config: Res<GameConfig>,
+) {
+    for ev in events.iter() {
+        let (mut health, mut invulnerability, mut controller, mut transform, mut velocity) =
+            match query.get_mut(ev.entity) {
+                Ok(player) => player,
+                Err(_) => continue,
+            };
 
-    if (velocity.linear.x <= max_x)
-        && (keyboard.pressed(KeyCode::D) || keyboard.pressed(KeyCode::Right))
-    {
-        velocity.linear.x += player.speed * PLAYER_SIZE * delta;
+        lives.0 = lives.0.saturating_sub(1);
+
+        if lives.0 == 0 {
+            if let Err(err) = state.set(AppState::GameOver) {
+                warn!("could not end the game: {:?}", err);
+            }
+            continue;
+        }
+
+        health.0 = config.player.health;
+        respawn(
+            &mut controller,
+            &mut transform,
+            &mut velocity,
+            respawn_point(&level, &checkpoint),
+        );
+        invulnerability.grant(config.player.respawn_invulnerability);
     }
 }
 
-fn reset_player(mut player_query: Query<(&mut Transform, &mut Velocity), With<Player>>) {
-    let (mut transform, mut velocity) = player_query.single_mut();
+/// Pushes the player away from whatever just hurt them
+fn knockback(
+    mut player_query: Query<(Entity, &Transform, &mut Velocity), With<Player>>,
+    source_query: Query<&Transform>,
+    mut events: EventReader<DamagedEvent>,
+    config: Res<GameConfig>,
+) {
+    let (entity, transform, mut velocity) = player_query.single_mut();
+
+    for ev in events.iter().filter(|ev| ev.target == entity) {
+        let source = ev.source.and_then(|source| source_query.get(source).ok());
+
+        // Without a source, push the player back the way they came
+        let away = match source {
+            Some(source) => transform.translation.x - source.translation.x,
+            None => -velocity.linear.x,
+        };
+        let direction = if away < 0.0 { -1.0 } else { 1.0 };
+
+        velocity.linear.x = direction * config.player.knockback.x;
+        velocity.linear.y = config.player.knockback.y;
+    }
+}
+
+/// Blinks the player while they can't be hurt
+fn flash_when_invulnerable(mut query: Query<(&Invulnerability, &mut Sprite), With<Player>>) {
+    const BLINK: f32 = 0.1;
+
+    for (invulnerability, mut sprite) in query.iter_mut() {
+        let visible = !invulnerability.active()
+            || (invulnerability.timer.elapsed_secs() / BLINK) as u32 % 2 == 1;
 
-    if transform.translation.y < -1.5 {
-        transform.translation = SPAWN_POINT.into();
-        let new_vel = Velocity::from_linear(Vec3::X * 0.0);
-        velocity.linear = new_vel.linear;
-        velocity.angular = new_vel.angular;
+        sprite.color.set_a(if visible { 1.0 } else { 0.2 });
     }
 }
@@ -0,0 +1,80 @@
+use bevy::{
+    app::PluginGroupBuilder, asset::AssetPlugin, hierarchy::HierarchyPlugin, input::InputPlugin,
+    prelude::*, transform::TransformPlugin, window::WindowPlugin,
+};
+use heron::PhysicsPlugin;
+
+use crate::{
+    camera::CameraBundle,
+    checkpoint::CheckpointPlugin,
+    collisions::CollisionsPlugin,
+    config::ConfigPlugin,
+    controller::ControllerPlugin,
+    enemy::EnemyPlugin,
+    enemy_kind::EnemyKindPlugin,
+    fireball::FireballPlugin,
+    goal::GoalPlugin,
+    ground::GroundPlugin,
+    health::HealthPlugin,
+    hud::HudEventsPlugin,
+    input::InputFramePlugin,
+    level::LevelPlugin,
+    pepper::PepperPlugin,
+    player::PlayerPlugin,
+    save::SavePlugin,
+    snapshot::SnapshotPlugin,
+    state::{AppState, StatePlugin},
+    stats::StatsPlugin,
+    terrain::TerrainPlugin,
+};
+
+/// Every plugin that makes up the game itself, without windowing, rendering or UI
+pub struct GameplayPlugins;
+
+impl PluginGroup for GameplayPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(ConfigPlugin)
+            .add(InputFramePlugin)
+            .add(LevelPlugin)
+            .add(TerrainPlugin)
+            .add(GoalPlugin)
+            .add(CheckpointPlugin)
+            .add(FireballPlugin)
+            .add(GroundPlugin)
+            .add(ControllerPlugin)
+            .add(PepperPlugin)
+            .add(PlayerPlugin)
+            .add(EnemyKindPlugin)
+            .add(EnemyPlugin)
+            .add(CollisionsPlugin)
+            .add(HealthPlugin)
+            .add(StatsPlugin)
+            .add(SavePlugin)
+            .add(SnapshotPlugin);
+    }
+}
+
+/// Runs the gameplay without a window or GPU, starting straight in `AppState::Playing`
+pub struct HeadlessPlugins;
+
+impl PluginGroup for HeadlessPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        MinimalPlugins.build(group);
+
+        group
+            .add(TransformPlugin::default())
+            .add(HierarchyPlugin::default())
+            .add(InputPlugin::default())
+            .add(WindowPlugin::default())
+            .add(AssetPlugin::default())
+            .add(PhysicsPlugin::default())
+            .add(HudEventsPlugin)
+            .add(StatePlugin {
+                initial: AppState::Playing,
+            })
+            .add(CameraBundle);
+
+        GameplayPlugins.build(group);
+    }
+}
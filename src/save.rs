@@ -0,0 +1,476 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    prelude::*,
+    window::{PresentMode, WindowMode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{level_file::CurrentLevel, state::AppState, stats::RunStats, terrain::TerrainSeed};
+
+/// Scores kept for each seed
+const HIGH_SCORES: usize = 5;
+/// Prefix of everything the game keeps in the browser's `localStorage`
+const STORAGE_PREFIX: &str = "kajam";
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<SaveStorage>() {
+            app.insert_resource(SaveStorage::default());
+        }
+
+        let save = SaveData::load(app.world.resource::<SaveStorage>());
+
+        app.insert_resource(save)
+            .add_startup_system(apply_settings)
+            .add_system_set(
+                SystemSet::on_enter(AppState::Victory)
+                    .with_system(record_completed_run.label(RecordRun)),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver)
+                    .with_system(record_failed_run.label(RecordRun)),
+            );
+    }
+}
+
+/// Adds the run that just ended to the save, screens showing high scores run after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemLabel)]
+pub struct RecordRun;
+
+/// Everything kept between runs.
+///
+/// Every field has a default, so saves from older versions load with whatever
+/// they are missing filled in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    /// Format the save was written in, see `SaveData::VERSION`
+    pub version: u32,
+    /// Best runs on each generated seed
+    pub seeds: BTreeMap<u64, SeedRecord>,
+    pub lifetime: LifetimeStats,
+    pub settings: Settings,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeedRecord {
+    /// Highest first
+    pub high_scores: Vec<u32>,
+    /// Fastest time to the goal in seconds
+    pub best_time: Option<f32>,
+}
+
+/// Totals of every run that has ended
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifetimeStats {
+    pub runs: u32,
+    pub levels_completed: u32,
+    pub time: f32,
+    pub distance: f32,
+    pub peppers: u32,
+    pub fireballs_fired: u32,
+    pub enemies_killed: u32,
+    pub damage_dealt: f32,
+    pub damage_taken: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub vsync: bool,
+    pub fullscreen: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            vsync: true,
+            fullscreen: false,
+        }
+    }
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        Self {
+            version: Self::VERSION,
+            seeds: BTreeMap::new(),
+            lifetime: LifetimeStats::default(),
+            settings: Settings::default(),
+        }
+    }
+}
+
+/// Just enough of a save to tell which format it's in
+#[derive(Deserialize)]
+struct Version {
+    /// Saves from before the format was versioned have none
+    #[serde(default)]
+    version: u32,
+}
+
+/// Format `text` was written in, 0 if it is from before formats were versioned
+pub(crate) fn read_version(text: &str) -> Result<u32, anyhow::Error> {
+    Ok(ron::de::from_str::<Version>(text)?.version)
+}
+
+/// Fails if `text` was written in a newer format than `latest`, which this
+/// version of the game can't know how to read
+pub(crate) fn check_version(text: &str, latest: u32) -> Result<(), anyhow::Error> {
+    let version = read_version(text)?;
+
+    if version > latest {
+        anyhow::bail!(
+            "version {} is newer than this game reads, up to {}",
+            version,
+            latest
+        );
+    }
+
+    Ok(())
+}
+
+impl SaveData {
+    /// Bumped whenever the format changes, with `parse` taught to read the old one
+    pub const VERSION: u32 = 1;
+    /// Name the save is kept under in `SaveStorage`
+    pub const NAME: &'static str = "save";
+
+    /// Reads a save in the current format or any older one
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        check_version(text, Self::VERSION)?;
+
+        // Fields have only been added so far, and those take their defaults
+        let mut save: SaveData = ron::de::from_str(text)?;
+        save.version = Self::VERSION;
+
+        Ok(save)
+    }
+
+    /// Loads the save, starting afresh if there is none or it can't be read
+    pub fn load(storage: &SaveStorage) -> Self {
+        let text = match storage.read(Self::NAME) {
+            Ok(Some(text)) => text,
+            Ok(None) => return Self::default(),
+            Err(err) => {
+                warn!("could not read save data: {}", err);
+                return Self::default();
+            }
+        };
+
+        match Self::parse(&text) {
+            Ok(save) => save,
+            Err(err) => {
+                warn!("save data can't be read, starting afresh: {}", err);
+
+                // Kept instead of being overwritten, in case it can be recovered
+                if let Err(err) = storage.back_up(Self::NAME, &text) {
+                    warn!("could not back up save data: {}", err);
+                }
+
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, storage: &mut SaveStorage) -> Result<(), anyhow::Error> {
+        let text = ron::ser::to_string_pretty(self, Default::default())?;
+        storage.write(Self::NAME, text)
+    }
+
+    /// Adds a run that has ended to the lifetime stats, and to the records of
+    /// its seed if it was played on a generated level
+    pub fn record(&mut self, seed: Option<u64>, stats: &RunStats, completed: bool) {
+        let lifetime = &mut self.lifetime;
+        lifetime.runs += 1;
+        lifetime.levels_completed += completed as u32;
+        lifetime.time += stats.time;
+        lifetime.distance += stats.distance;
+        lifetime.peppers += stats.peppers;
+        lifetime.fireballs_fired += stats.fireballs_fired;
+        lifetime.enemies_killed += stats.enemies_killed;
+        lifetime.damage_dealt += stats.damage_dealt;
+        lifetime.damage_taken += stats.damage_taken;
+
+        let record = match seed {
+            Some(seed) => self.seeds.entry(seed).or_default(),
+            None => return,
+        };
+
+        record.high_scores.push(stats.score());
+        record.high_scores.sort_unstable_by(|a, b| b.cmp(a));
+        record.high_scores.truncate(HIGH_SCORES);
+
+        if completed && !matches!(record.best_time, Some(best) if best <= stats.time) {
+            record.best_time = Some(stats.time);
+        }
+    }
+}
+
+/// Where saves are kept, each under its own name
+pub enum SaveStorage {
+    /// Files in the game's folder of the user's data directory
+    Dir(PathBuf),
+    /// The browser's `localStorage`
+    LocalStorage,
+    /// Nothing outlives the app, for tests and headless runs
+    Memory(HashMap<String, String>),
+}
+
+impl Default for SaveStorage {
+    fn default() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            return SaveStorage::LocalStorage;
+        }
+
+        match data_dir() {
+            Some(dir) => SaveStorage::Dir(dir.join("kajam")),
+            None => {
+                warn!("no data directory found, nothing will be saved");
+                SaveStorage::Memory(HashMap::new())
+            }
+        }
+    }
+}
+
+impl SaveStorage {
+    /// The text saved under `name`, `None` if nothing has been saved there yet
+    pub fn read(&self, name: &str) -> Result<Option<String>, anyhow::Error> {
+        match self {
+            SaveStorage::Dir(dir) => match std::fs::read_to_string(file_path(dir, name)) {
+                Ok(text) => Ok(Some(text)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err.into()),
+            },
+            SaveStorage::LocalStorage => local_storage::get(&storage_key(name)),
+            SaveStorage::Memory(saved) => Ok(saved.get(name).cloned()),
+        }
+    }
+
+    pub fn write(&mut self, name: &str, text: String) -> Result<(), anyhow::Error> {
+        match self {
+            SaveStorage::Dir(dir) => {
+                std::fs::create_dir_all(dir.as_path())?;
+                std::fs::write(file_path(dir, name), text)?;
+                Ok(())
+            }
+            SaveStorage::LocalStorage => local_storage::set(&storage_key(name), &text),
+            SaveStorage::Memory(saved) => {
+                saved.insert(name.to_string(), text);
+                Ok(())
+            }
+        }
+    }
+
+    /// Deletes whatever is saved under `name`, nothing being there is not an error
+    pub fn remove(&mut self, name: &str) -> Result<(), anyhow::Error> {
+        match self {
+            SaveStorage::Dir(dir) => match std::fs::remove_file(file_path(dir, name)) {
+                Ok(()) => Ok(()),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(err) => Err(err.into()),
+            },
+            SaveStorage::LocalStorage => local_storage::remove(&storage_key(name)),
+            SaveStorage::Memory(saved) => {
+                saved.remove(name);
+                Ok(())
+            }
+        }
+    }
+
+    /// Copies a save that can't be read next to it
+    fn back_up(&self, name: &str, text: &str) -> Result<(), anyhow::Error> {
+        match self {
+            SaveStorage::Dir(dir) => {
+                std::fs::write(dir.join(format!("{}.ron.bak", name)), text)?;
+                Ok(())
+            }
+            SaveStorage::LocalStorage => {
+                local_storage::set(&format!("{}.bak", storage_key(name)), text)
+            }
+            SaveStorage::Memory(_) => Ok(()),
+        }
+    }
+}
+
+fn file_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.ron", name))
+}
+
+fn storage_key(name: &str) -> String {
+    format!("{}.{}", STORAGE_PREFIX, name)
+}
+
+/// Where each platform keeps application data
+fn data_dir() -> Option<PathBuf> {
+    let var = |name: &str| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+
+    if cfg!(target_os = "windows") {
+        var("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        var("XDG_DATA_HOME").or_else(|| var("HOME").map(|home| home.join(".local").join("share")))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod local_storage {
+    use anyhow::anyhow;
+
+    fn storage() -> Result<web_sys::Storage, anyhow::Error> {
+        web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or_else(|| anyhow!("localStorage is not available"))
+    }
+
+    pub fn get(key: &str) -> Result<Option<String>, anyhow::Error> {
+        storage()?
+            .get_item(key)
+            .map_err(|err| anyhow!("could not read localStorage: {:?}", err))
+    }
+
+    pub fn set(key: &str, value: &str) -> Result<(), anyhow::Error> {
+        storage()?
+            .set_item(key, value)
+            .map_err(|err| anyhow!("could not write localStorage: {:?}", err))
+    }
+
+    pub fn remove(key: &str) -> Result<(), anyhow::Error> {
+        storage()?
+            .remove_item(key)
+            .map_err(|err| anyhow!("could not write localStorage: {:?}", err))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod local_storage {
+    pub fn get(_key: &str) -> Result<Option<String>, anyhow::Error> {
+        anyhow::bail!("localStorage is only available on the web")
+    }
+
+    pub fn set(_key: &str, _value: &str) -> Result<(), anyhow::Error> {
+        anyhow::bail!("localStorage is only available on the web")
+    }
+
+    pub fn remove(_key: &str) -> Result<(), anyhow::Error> {
+        anyhow::bail!("localStorage is only available on the web")
+    }
+}
+
+fn apply_settings(save: Res<SaveData>, mut windows: ResMut<Windows>) {
+    let window = match windows.get_primary_mut() {
+        Some(window) => window,
+        None => return,
+    };
+
+    window.set_present_mode(if save.settings.vsync {
+        PresentMode::Fifo
+    } else {
+        PresentMode::Immediate
+    });
+    window.set_mode(if save.settings.fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    });
+}
+
+fn record_completed_run(
+    save: ResMut<SaveData>,
+    storage: ResMut<SaveStorage>,
+    seed: Res<TerrainSeed>,
+    level: Res<CurrentLevel>,
+    stats: Res<RunStats>,
+) {
+    record_run(save, storage, &seed, &level, &stats, true);
+}
+
+fn record_failed_run(
+    save: ResMut<SaveData>,
+    storage: ResMut<SaveStorage>,
+    seed: Res<TerrainSeed>,
+    level: Res<CurrentLevel>,
+    stats: Res<RunStats>,
+) {
+    record_run(save, storage, &seed, &level, &stats, false);
+}
+
+fn record_run(
+    mut save: ResMut<SaveData>,
+    mut storage: ResMut<SaveStorage>,
+    seed: &TerrainSeed,
+    level: &CurrentLevel,
+    stats: &RunStats,
+    completed: bool,
+) {
+    // Hand-made levels don't have a seed to keep records for
+    let seed = match level.0 {
+        Some(_) => None,
+        None => Some(seed.0),
+    };
+    save.record(seed, stats, completed);
+
+    if let Err(err) = save.save(&mut storage) {
+        error!("could not save: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_round_trip() {
+        let mut save = SaveData::default();
+        save.record(Some(7), &RunStats::default(), true);
+
+        let text = ron::ser::to_string_pretty(&save, Default::default()).unwrap();
+        assert_eq!(SaveData::parse(&text).unwrap(), save);
+    }
+
+    #[test]
+    fn old_saves_load_and_new_or_corrupt_ones_do_not() {
+        let old = SaveData::parse("(lifetime: (runs: 3))").unwrap();
+        assert_eq!(old.version, SaveData::VERSION);
+        assert_eq!(old.lifetime.runs, 3);
+        assert_eq!(old.settings, Settings::default());
+
+        assert!(SaveData::parse("(version: 999)").is_err());
+        assert!(SaveData::parse("(lifetime: (runs: \"three\"))").is_err());
+        assert!(SaveData::parse("not a save").is_err());
+    }
+
+    #[test]
+    fn high_scores_keep_the_best_few() {
+        let mut save = SaveData::default();
+
+        for points in 0..10 {
+            let stats = RunStats {
+                points: points * 100,
+                time: 100.0 - points as f32,
+                ..Default::default()
+            };
+            save.record(Some(1), &stats, points % 2 == 0);
+        }
+        save.record(None, &RunStats::default(), false);
+
+        let record = &save.seeds[&1];
+        assert_eq!(record.high_scores, vec![900, 800, 700, 600, 500]);
+        assert_eq!(record.best_time, Some(92.0));
+        assert_eq!(save.lifetime.runs, 11);
+        assert_eq!(save.lifetime.levels_completed, 5);
+    }
+}
@@ -0,0 +1,445 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+use heron::Velocity;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::GameAssets,
+    checkpoint::{Checkpoint, CheckpointPost},
+    config::GameConfig,
+    controller::ControllerState,
+    enemy::{spawn_enemy, BrainState, Enemy, EnemyBrain},
+    enemy_kind::EnemyKinds,
+    fireball::{spawn_fireball, Faction, Fireball, PepperTimer},
+    health::Health,
+    hud::UpdatePepperCountEvent,
+    level::{LevelNumber, RestartLevelEvent},
+    level_file::{CurrentLevel, LevelFile},
+    pepper::{spawn_pepper, Pepper},
+    player::{Lives, Player},
+    save::{read_version, SaveStorage},
+    state::AppState,
+    stats::RunStats,
+    terrain::{Chunk, ChunkMember, ChunkRecord, LevelLength, Terrain, TerrainSeed},
+};
+
+pub struct SnapshotPlugin;
+
+impl Plugin for SnapshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveSnapshotEvent>()
+            .add_event::<LoadSnapshotEvent>()
+            .init_resource::<PendingSnapshot>()
+            // Before the level restarts in `Update`, so the restart uses the snapshot's seed
+            .add_system_to_stage(CoreStage::PreUpdate, load_snapshot)
+            // After the restart, which would otherwise undo the restored state
+            .add_system_to_stage(CoreStage::PostUpdate, restore_snapshot)
+            // After `Update` so a save asked for just before quitting is written before the app exits
+            .add_system_to_stage(CoreStage::PostUpdate, save_snapshot)
+            // A finished run can't be carried on
+            .add_system_set(SystemSet::on_enter(AppState::Victory).with_system(discard_snapshot))
+            .add_system_set(SystemSet::on_enter(AppState::GameOver).with_system(discard_snapshot));
+    }
+}
+
+/// Writes a snapshot of the run to the `SaveStorage`
+pub struct SaveSnapshotEvent;
+
+/// Throws the current run away and carries on from the snapshot instead
+pub struct LoadSnapshotEvent(pub Snapshot);
+
+/// A run part way through a level, enough to rebuild the world and carry on playing.
+///
+/// Peppers and enemies are only kept for the chunks in the world when it was
+/// taken, the rest of the level places its own as it streams in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Format the snapshot was written in, see `Snapshot::VERSION`
+    pub version: u32,
+    pub seed: u64,
+    pub length: LevelLength,
+    pub level_number: u32,
+    /// Hand-made level being played, kept whole so later edits to its file
+    /// can't break the snapshot
+    pub level: Option<LevelFile>,
+    pub player: PlayerSnapshot,
+    pub lives: u32,
+    pub checkpoint: Option<CheckpointPost>,
+    pub stats: RunStats,
+    /// Seconds the pepper being used has been burning for
+    pub pepper_timer: f32,
+    /// Chunks that were in the world
+    pub chunks: Vec<u32>,
+    /// Peppers collected and enemies killed in each chunk
+    pub records: HashMap<u32, ChunkRecord>,
+    pub peppers: Vec<PepperSnapshot>,
+    pub enemies: Vec<EnemySnapshot>,
+    pub fireballs: Vec<FireballSnapshot>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    pub translation: Vec3,
+    pub velocity: Vec3,
+    pub health: f32,
+    pub peppers: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PepperSnapshot {
+    pub chunk: u32,
+    pub block: u32,
+    pub translation: Vec3,
+    pub id: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnemySnapshot {
+    pub chunk: u32,
+    pub block: u32,
+    /// Name of its `EnemyKind`
+    pub kind: String,
+    pub translation: Vec3,
+    pub velocity: Vec3,
+    pub health: f32,
+    pub state: BrainState,
+    pub patrol: (f32, f32),
+    pub heading: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FireballSnapshot {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub velocity: Vec3,
+    pub faction: Faction,
+}
+
+impl Snapshot {
+    /// Bumped whenever the format changes, snapshots written in any other version aren't read
+    pub const VERSION: u32 = 1;
+    /// Name the snapshot is kept under in `SaveStorage`
+    pub const NAME: &'static str = "run";
+
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let version = read_version(text)?;
+        if version != Self::VERSION {
+            anyhow::bail!(
+                "version {} snapshots can't be read, only version {}",
+                version,
+                Self::VERSION
+            );
+        }
+
+        Ok(ron::de::from_str(text)?)
+    }
+
+    /// The saved run, `None` if there isn't one or it can't be read
+    pub fn load(storage: &SaveStorage) -> Option<Self> {
+        let text = match storage.read(Self::NAME) {
+            Ok(text) => text?,
+            Err(err) => {
+                warn!("could not read the saved run: {}", err);
+                return None;
+            }
+        };
+
+        match Self::parse(&text) {
+            Ok(snapshot) => Some(snapshot),
+            Err(err) => {
+                warn!("saved run can't be read: {}", err);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, storage: &mut SaveStorage) -> Result<(), anyhow::Error> {
+        let text = ron::ser::to_string_pretty(self, Default::default())?;
+        storage.write(Self::NAME, text)
+    }
+
+    /// Deletes the saved run, if there is one
+    pub fn delete(storage: &mut SaveStorage) {
+        if let Err(err) = storage.remove(Self::NAME) {
+            warn!("could not delete the saved run: {}", err);
+        }
+    }
+}
+
+/// Snapshot being loaded, waiting for the level to restart before it's put back
+#[derive(Default)]
+struct PendingSnapshot(Option<Snapshot>);
+
+fn save_snapshot(
+    mut events: EventReader<SaveSnapshotEvent>,
+    mut storage: ResMut<SaveStorage>,
+    seed: Res<TerrainSeed>,
+    length: Res<LevelLength>,
+    number: Res<LevelNumber>,
+    level: Res<CurrentLevel>,
+    lives: Res<Lives>,
+    checkpoint: Res<Checkpoint>,
+    stats: Res<RunStats>,
+    timer: Res<PepperTimer>,
+    terrain: Res<Terrain>,
+    player_query: Query<(&Player, &Transform, &Velocity, &Health)>,
+    chunk_query: Query<&Chunk>,
+    pepper_query: Query<(&Pepper, &ChunkMember, &Transform)>,
+    enemy_query: Query<(
+        &Name,
+        &Enemy,
+        &ChunkMember,
+        &Transform,
+        &Velocity,
+        &Health,
+        &EnemyBrain,
+    )>,
+    fireball_query: Query<(&Fireball, &Transform, &Velocity)>,
+) {
+    if events.iter().last().is_none() {
+        return;
+    }
+
+    let (player, transform, velocity, health) = player_query.single();
+
+    let snapshot = Snapshot {
+        version: Snapshot::VERSION,
+        seed: seed.0,
+        length: *length,
+        level_number: number.0,
+        level: level.0.clone(),
+        player: PlayerSnapshot {
+            translation: transform.translation,
+            velocity: velocity.linear,
+            health: health.0,
+            peppers: player.peppers,
+        },
+        lives: lives.0,
+        checkpoint: checkpoint.0,
+        stats: *stats,
+        pepper_timer: timer.timer.elapsed_secs(),
+        chunks: chunk_query.iter().map(|chunk| chunk.0).collect(),
+        records: terrain.records().clone(),
+        peppers: pepper_query
+            .iter()
+            .map(|(pepper, member, transform)| PepperSnapshot {
+                chunk: member.0,
+                block: pepper.block,
+                translation: transform.translation,
+                id: pepper.id,
+            })
+            .collect(),
+        enemies: enemy_query
+            .iter()
+            .map(
+                |(name, enemy, member, transform, velocity, health, brain)| EnemySnapshot {
+                    chunk: member.0,
+                    block: enemy.block,
+                    kind: name.as_str().to_string(),
+                    translation: transform.translation,
+                    velocity: velocity.linear,
+                    health: health.0,
+                    state: brain.state,
+                    patrol: brain.patrol,
+                    heading: brain.heading,
+                },
+            )
+            .collect(),
+        fireballs: fireball_query
+            .iter()
+            .map(|(fireball, transform, velocity)| FireballSnapshot {
+                translation: transform.translation,
+                rotation: transform.rotation,
+                velocity: velocity.linear,
+                faction: fireball.faction,
+            })
+            .collect(),
+    };
+
+    match snapshot.save(&mut storage) {
+        Ok(()) => info!("saved the run at {}", snapshot.player.translation),
+        Err(err) => error!("could not save the run: {}", err),
+    }
+}
+
+/// Switches to the snapshot's level and restarts it, `restore_snapshot` puts
+/// everything else back once the restart is done. The saved run is deleted so
+/// it can only be carried on once.
+fn load_snapshot(
+    mut events: EventReader<LoadSnapshotEvent>,
+    mut storage: ResMut<SaveStorage>,
+    mut pending: ResMut<PendingSnapshot>,
+    mut seed: ResMut<TerrainSeed>,
+    mut length: ResMut<LevelLength>,
+    mut number: ResMut<LevelNumber>,
+    mut level: ResMut<CurrentLevel>,
+    mut terrain: ResMut<Terrain>,
+    mut restart: EventWriter<RestartLevelEvent>,
+) {
+    let snapshot = match events.iter().last() {
+        Some(ev) => ev.0.clone(),
+        None => return,
+    };
+
+    Snapshot::delete(&mut storage);
+
+    seed.0 = snapshot.seed;
+    *length = snapshot.length;
+    number.0 = snapshot.level_number;
+    level.0 = snapshot.level.clone();
+
+    // The snapshot's own peppers and enemies go in these chunks instead
+    terrain.restore(snapshot.chunks.iter().copied());
+
+    pending.0 = Some(snapshot);
+    restart.send(RestartLevelEvent);
+}
+
+fn restore_snapshot(
+    mut commands: Commands,
+    mut pending: ResMut<PendingSnapshot>,
+    assets: Option<Res<GameAssets>>,
+    config: Res<GameConfig>,
+    kinds: Res<EnemyKinds>,
+    mut lives: ResMut<Lives>,
+    mut checkpoint: ResMut<Checkpoint>,
+    mut stats: ResMut<RunStats>,
+    mut timer: ResMut<PepperTimer>,
+    mut terrain: ResMut<Terrain>,
+    mut player_query: Query<(
+        &mut Player,
+        &mut ControllerState,
+        &mut Transform,
+        &mut Velocity,
+        &mut Health,
+    )>,
+    mut pepper_event: EventWriter<UpdatePepperCountEvent>,
+) {
+    let snapshot = match pending.0.take() {
+        Some(snapshot) => snapshot,
+        None => return,
+    };
+
+    lives.0 = snapshot.lives;
+    checkpoint.0 = snapshot.checkpoint;
+    *stats = snapshot.stats;
+    timer
+        .timer
+        .set_elapsed(Duration::from_secs_f32(snapshot.pepper_timer));
+    // The restart cleared them
+    terrain.set_records(snapshot.records.clone());
+
+    let (mut player, mut state, mut transform, mut velocity, mut health) =
+        player_query.single_mut();
+    player.peppers = snapshot.player.peppers;
+    *state = ControllerState::default();
+    transform.translation = snapshot.player.translation;
+    *velocity = Velocity::from_linear(snapshot.player.velocity);
+    health.0 = snapshot.player.health;
+    pepper_event.send(UpdatePepperCountEvent(player.peppers));
+
+    for pepper in snapshot.peppers.iter() {
+        spawn_pepper(
+            &mut commands,
+            assets.as_deref(),
+            pepper.chunk,
+            pepper.translation,
+            Pepper {
+                id: pepper.id,
+                block: pepper.block,
+            },
+        );
+    }
+
+    for enemy in snapshot.enemies.iter() {
+        let kind = match kinds.kinds.iter().find(|kind| kind.name == enemy.kind) {
+            Some(kind) => kind,
+            None => {
+                warn!("saved run has an unknown enemy kind {}", enemy.kind);
+                continue;
+            }
+        };
+
+        let mut brain = EnemyBrain::new(&config, kind, enemy.translation.x, enemy.heading);
+        brain.state = enemy.state;
+        brain.patrol = enemy.patrol;
+
+        let entity = spawn_enemy(
+            &mut commands,
+            &config,
+            kind,
+            enemy.chunk,
+            enemy.block,
+            enemy.translation,
+            brain,
+        );
+        commands
+            .entity(entity)
+            .insert(Health(enemy.health))
+            .insert(Velocity::from_linear(enemy.velocity));
+    }
+
+    for fireball in snapshot.fireballs.iter() {
+        let transform = Transform {
+            translation: fireball.translation,
+            rotation: fireball.rotation,
+            ..Default::default()
+        };
+
+        spawn_fireball(
+            &mut commands,
+            assets.as_deref(),
+            transform,
+            fireball.velocity,
+            fireball.faction,
+        );
+    }
+
+    info!("resumed the run at {}", snapshot.player.translation);
+}
+
+fn discard_snapshot(mut storage: ResMut<SaveStorage>) {
+    Snapshot::delete(&mut storage);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(version: u32) -> String {
+        let snapshot = Snapshot {
+            version,
+            seed: 7,
+            length: LevelLength::Endless,
+            level_number: 0,
+            level: None,
+            player: PlayerSnapshot {
+                translation: Vec3::ZERO,
+                velocity: Vec3::ZERO,
+                health: 1.0,
+                peppers: 0,
+            },
+            lives: 3,
+            checkpoint: None,
+            stats: RunStats::default(),
+            pepper_timer: 0.0,
+            chunks: vec![0],
+            records: HashMap::new(),
+            peppers: Vec::new(),
+            enemies: Vec::new(),
+            fireballs: Vec::new(),
+        };
+
+        ron::ser::to_string(&snapshot).unwrap()
+    }
+
+    #[test]
+    fn snapshots_from_other_versions_are_rejected() {
+        assert!(Snapshot::parse(&snapshot(Snapshot::VERSION)).is_ok());
+
+        assert!(Snapshot::parse(&snapshot(0)).is_err());
+        assert!(Snapshot::parse(&snapshot(Snapshot::VERSION + 1)).is_err());
+        assert!(Snapshot::parse("(version: 1)").is_err());
+    }
+}
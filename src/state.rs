@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+use heron::PhysicsTime;
+
+use crate::input_map::{Action, InputDevices, InputDevicesMut, InputMap};
+
+pub struct StatePlugin {
+    pub initial: AppState,
+}
+
+impl Default for StatePlugin {
+    fn default() -> Self {
+        Self {
+            initial: AppState::MainMenu,
+        }
+    }
+}
+
+impl Plugin for StatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(self.initial)
+            .add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(pause_physics))
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(resume_physics))
+            .add_system_set(SystemSet::on_resume(AppState::Playing).with_system(resume_physics))
+            .add_system_set(SystemSet::on_exit(AppState::Playing).with_system(pause_physics))
+            .add_system_set(SystemSet::on_pause(AppState::Playing).with_system(pause_physics))
+            .add_system(toggle_pause);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppState {
+    MainMenu,
+    Playing,
+    Paused,
+    GameOver,
+    Victory,
+    /// Level editor, only reachable in debug builds
+    Editing,
+}
+
+fn resume_physics(mut physics_time: ResMut<PhysicsTime>) {
+    physics_time.resume();
+}
+
+fn pause_physics(mut physics_time: ResMut<PhysicsTime>) {
+    physics_time.pause();
+}
+
+fn toggle_pause(
+    map: Res<InputMap>,
+    mut devices: ParamSet<(InputDevices, InputDevicesMut)>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if !map.just_pressed(Action::Pause, &devices.p0()) {
+        return;
+    }
+
+    let result = match state.current() {
+        AppState::Playing => state.push(AppState::Paused),
+        AppState::Paused => state.pop(),
+        _ => return,
+    };
+
+    if let Err(err) = result {
+        warn!("could not toggle pause: {:?}", err);
+    }
+
+    // Stops the key from being read again when the stage re-runs for the transition
+    let mut devices = devices.p1();
+    for (action, binding) in map.bindings.iter() {
+        if *action == Action::Pause {
+            devices.reset(binding);
+        }
+    }
+}
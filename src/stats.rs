@@ -0,0 +1,229 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    enemy::Enemy,
+    fireball::{Faction, Fireball},
+    health::{ApplyDamage, DamageKind, DamagedEvent, DiedEvent},
+    hud::UpdateScoreEvent,
+    input::InputFrame,
+    level::RestartLevelEvent,
+    pepper::PepperCollectedEvent,
+    player::Player,
+    state::AppState,
+};
+
+/// Points for every pepper picked up
+const PEPPER_POINTS: u32 = 50;
+/// Points for a kill, multiplied by the combo
+const KILL_POINTS: u32 = 100;
+/// Points for every world unit further right the player has been
+const DISTANCE_POINTS: f32 = 10.0;
+/// Seconds after a kill the next one keeps the combo going
+const COMBO_TIME: f32 = 3.0;
+const MAX_COMBO: u32 = 8;
+/// Movement in one tick further than this is a respawn, not travel
+const MAX_STEP: f32 = 0.5;
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunStats>()
+            .add_system(reset_stats)
+            .add_system(report_score)
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(track_time)
+                    .with_system(track_distance)
+                    .with_system(track_peppers)
+                    .with_system(track_fireballs)
+                    // While the dead enemies are still around to tell them from the player
+                    .with_system(track_damage.after(ApplyDamage)),
+            );
+    }
+}
+
+/// How the current attempt at the level has gone, shown on the HUD and results screen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunStats {
+    /// Seconds of play, pauses and menus don't count
+    pub time: f32,
+    /// World units walked, jumped and fallen sideways
+    pub distance: f32,
+    /// Furthest right the player has been
+    pub furthest: f32,
+    pub peppers: u32,
+    pub fireballs_fired: u32,
+    /// Fireballs that hurt an enemy
+    pub fireballs_hit: u32,
+    pub enemies_killed: u32,
+    pub damage_dealt: f32,
+    pub damage_taken: f32,
+    /// Points from peppers and kills, distance is added on in `score`
+    pub points: u32,
+    /// Kills in a row, each one worth more than the last
+    pub combo: u32,
+    /// Seconds left before the combo is lost
+    pub combo_time: f32,
+}
+
+impl RunStats {
+    pub fn score(&self) -> u32 {
+        self.points + (self.furthest.max(0.0) * DISTANCE_POINTS) as u32
+    }
+
+    /// What the next kill is multiplied by
+    pub fn multiplier(&self) -> u32 {
+        (self.combo + 1).min(MAX_COMBO)
+    }
+
+    /// Fraction of fireballs that hit, `0` before the first one is fired
+    pub fn hit_rate(&self) -> f32 {
+        if self.fireballs_fired == 0 {
+            return 0.0;
+        }
+
+        self.fireballs_hit as f32 / self.fireballs_fired as f32
+    }
+
+    pub fn add_pepper(&mut self) {
+        self.peppers += 1;
+        self.points += PEPPER_POINTS;
+    }
+
+    pub fn add_kill(&mut self) {
+        self.points += KILL_POINTS * self.multiplier();
+        self.enemies_killed += 1;
+        self.combo += 1;
+        self.combo_time = COMBO_TIME;
+    }
+
+    /// Counts down the combo, which is lost once it runs out
+    pub fn tick(&mut self, delta: f32) {
+        self.time += delta;
+        self.combo_time = (self.combo_time - delta).max(0.0);
+
+        if self.combo_time == 0.0 {
+            self.combo = 0;
+        }
+    }
+}
+
+fn reset_stats(mut stats: ResMut<RunStats>, mut events: EventReader<RestartLevelEvent>) {
+    if events.iter().last().is_some() {
+        *stats = RunStats::default();
+    }
+}
+
+fn track_time(mut stats: ResMut<RunStats>, input: Res<InputFrame>) {
+    stats.tick(input.delta);
+}
+
+fn track_distance(
+    mut stats: ResMut<RunStats>,
+    mut last: Local<Option<Vec2>>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let position = player_query.single().translation.truncate();
+
+    if let Some(last) = *last {
+        let step = (position.x - last.x).abs();
+
+        if step < MAX_STEP {
+            stats.distance += step;
+        }
+    }
+
+    stats.furthest = stats.furthest.max(position.x);
+    *last = Some(position);
+}
+
+fn track_peppers(mut stats: ResMut<RunStats>, mut events: EventReader<PepperCollectedEvent>) {
+    for _ in events.iter() {
+        stats.add_pepper();
+    }
+}
+
+fn track_fireballs(mut stats: ResMut<RunStats>, query: Query<&Fireball, Added<Fireball>>) {
+    for fireball in query.iter() {
+        if fireball.faction == Faction::Player {
+            stats.fireballs_fired += 1;
+        }
+    }
+}
+
+fn track_damage(
+    mut stats: ResMut<RunStats>,
+    player_query: Query<(), With<Player>>,
+    enemy_query: Query<(), With<Enemy>>,
+    mut damaged: EventReader<DamagedEvent>,
+    mut died: EventReader<DiedEvent>,
+) {
+    for ev in damaged.iter() {
+        if player_query.contains(ev.target) {
+            stats.damage_taken += ev.amount;
+        } else if enemy_query.contains(ev.target) {
+            stats.damage_dealt += ev.amount;
+
+            if ev.kind == DamageKind::Fireball {
+                stats.fireballs_hit += 1;
+            }
+        }
+    }
+
+    for ev in died.iter() {
+        if enemy_query.contains(ev.entity) {
+            stats.add_kill();
+        }
+    }
+}
+
+fn report_score(
+    stats: Res<RunStats>,
+    mut last: Local<Option<(u32, u32)>>,
+    mut events: EventWriter<UpdateScoreEvent>,
+) {
+    let current = (stats.score(), stats.multiplier());
+
+    if *last != Some(current) {
+        events.send(UpdateScoreEvent {
+            score: current.0,
+            multiplier: current.1,
+        });
+        *last = Some(current);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kills_in_a_row_are_worth_more() {
+        let mut stats = RunStats::default();
+
+        stats.add_kill();
+        stats.tick(1.0);
+        stats.add_kill();
+        assert_eq!(stats.points, KILL_POINTS * 3);
+        assert_eq!(stats.multiplier(), 3);
+
+        stats.tick(COMBO_TIME);
+        assert_eq!(stats.multiplier(), 1);
+
+        stats.add_kill();
+        assert_eq!(stats.points, KILL_POINTS * 4);
+    }
+
+    #[test]
+    fn combo_multiplier_is_capped() {
+        let mut stats = RunStats::default();
+
+        for _ in 0..20 {
+            stats.add_kill();
+        }
+
+        assert_eq!(stats.multiplier(), MAX_COMBO);
+    }
+}
@@ -1,73 +1,591 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
 use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
 use heron::prelude::*;
-use noise::NoiseFn;
-use rand::Rng;
-use std::time::{SystemTime, UNIX_EPOCH};
+use noise::{NoiseFn, Seedable};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    block_type::{BlockType, BLOCK_HEIGHT, BLOCK_SIZE},
-    utils::Layers,
+    block_type::{BlockType, BLOCK_SIZE},
+    camera::MainCamera,
+    config::{GameConfig, TerrainConfig},
+    ground::Grounded,
+    level::RestartLevelEvent,
+    level_file::{apply_level_file, load_level_file, CurrentLevel, LevelFile, LevelFileLoader},
+    state::AppState,
+    traversal::{self, Reach},
+    utils::{arg_value, has_arg, Layers},
 };
 
 pub const MAP_LEN: u32 = 1000;
+/// Blocks in each piece of terrain that is streamed in and out
+pub const CHUNK_LEN: u32 = 50;
+/// Chunks are kept loaded this far either side of the camera
+const STREAM_DISTANCE: f32 = CHUNK_LEN as f32 * BLOCK_SIZE;
+
+/// Gaps, platforms, spikes and bounce pads are placed at most once every this many blocks
+const FEATURE_SPACING: u32 = 10;
+/// Flat blocks before each feature
+const FEATURE_OFFSET: usize = 3;
+/// Blocks before the first feature, so the player doesn't spawn next to one
+const FEATURE_START: u32 = 20;
+const FEATURE_CHANCE: f64 = 0.4;
+/// Rolls for a feature the player can get past before giving up on the stretch
+const FEATURE_ATTEMPTS: usize = 4;
+/// "features" in ASCII
+const FEATURE_SALT: u64 = 0x6665_6174_7572_6573;
+/// "heights" in ASCII
+const HEIGHT_SALT: u64 = 0x0068_6569_6768_7473;
+/// Widest gap in blocks, small enough to always be jumped
+const MAX_GAP: usize = 3;
+const MAX_SPIKES: usize = 3;
+
+/// How far above the ground platforms are, low enough to always be jumped onto
+pub const PLATFORM_HEIGHT: f32 = 0.25;
+const PLATFORM_THICKNESS: f32 = 0.03;
+const SPIKE_HEIGHT: f32 = 0.04;
+const PAD_HEIGHT: f32 = 0.02;
+/// How far below the ground a gap's pit starts
+const PIT_DEPTH: f32 = 0.5;
+
 pub struct TerrainPlugin;
 
 impl Plugin for TerrainPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Terrain>()
-            .add_startup_system(generate_terrain);
+            .init_resource::<TerrainSeed>()
+            .init_resource::<LevelLength>()
+            .init_resource::<CurrentLevel>()
+            .add_asset::<LevelFile>()
+            .init_asset_loader::<LevelFileLoader>()
+            .add_event::<ChunkSpawnedEvent>()
+            .add_event::<ChunkDespawnedEvent>()
+            .add_event::<FellInPitEvent>()
+            .add_startup_system(load_level_file)
+            .add_startup_system(generate_terrain)
+            .add_system(apply_level_file)
+            .add_system(restart_terrain)
+            .add_system(stream_chunks)
+            .add_system(despawn_chunk_members)
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(one_way_platforms));
     }
 }
 
+/// How many blocks the level has, `--endless` keeps generating terrain forever
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LevelLength {
+    Blocks(u32),
+    Endless,
+}
+
+impl LevelLength {
+    pub fn blocks(&self) -> Option<u32> {
+        match self {
+            LevelLength::Blocks(blocks) => Some(*blocks),
+            LevelLength::Endless => None,
+        }
+    }
+
+    fn chunks(&self) -> Option<u32> {
+        self.blocks()
+            .map(|blocks| (blocks + CHUNK_LEN - 1) / CHUNK_LEN)
+    }
+}
+
+impl Default for LevelLength {
+    fn default() -> Self {
+        if has_arg("--endless") {
+            LevelLength::Endless
+        } else {
+            LevelLength::Blocks(MAP_LEN)
+        }
+    }
+}
+
+/// Chunks generated so far and the ones currently in the world
 #[derive(Default)]
-pub struct Terrain(Vec<Entity>);
+pub struct Terrain {
+    /// Height of the ground where each chunk starts, chunks are generated in
+    /// order so heights carry over
+    starts: Vec<f32>,
+    loaded: HashMap<u32, Entity>,
+    /// Chunks whose peppers and enemies were restored from a snapshot, see `restore`
+    restored: HashSet<u32>,
+    /// What has been taken out of each chunk, kept while the chunk is unloaded
+    records: HashMap<u32, ChunkRecord>,
+}
 
-fn generate_terrain(mut commands: Commands, mut terrain: ResMut<Terrain>) {
-    let heights = generate_heightmap(MAP_LEN);
-    let raw_blocks = heightmap_to_blocks(heights);
-    let blocks = process_blocks(raw_blocks);
+/// Peppers collected and enemies killed in a chunk, which stay gone when it streams back in
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChunkRecord {
+    /// Blocks the collected peppers were placed above
+    pub peppers: HashSet<u32>,
+    /// Blocks the killed enemies were placed on
+    pub enemies: HashSet<u32>,
+}
 
-    let mut y = 0.0;
-    let mut x = -BLOCK_SIZE;
+impl Terrain {
+    pub fn is_loaded(&self, chunk: u32) -> bool {
+        self.loaded.contains_key(&chunk)
+    }
+
+    /// Marks chunks whose peppers and enemies are put back from a snapshot,
+    /// so they aren't placed again when the chunk next spawns
+    pub fn restore(&mut self, chunks: impl IntoIterator<Item = u32>) {
+        self.restored.extend(chunks);
+    }
 
-    for (i, (block, new_y)) in blocks.iter().enumerate() {
-        let color = color_block(i, blocks.len());
+    pub fn record(&mut self, chunk: u32) -> &mut ChunkRecord {
+        self.records.entry(chunk).or_default()
+    }
 
-        y += new_y;
-        x += BLOCK_SIZE;
+    pub fn records(&self) -> &HashMap<u32, ChunkRecord> {
+        &self.records
+    }
 
-        let (x_offset, y_offset) = match block {
-            BlockType::Flat => {
-                if i == 0 {
-                    (x, y)
-                } else {
-                    let (prev, _) = blocks[i - 1];
+    pub fn set_records(&mut self, records: HashMap<u32, ChunkRecord>) {
+        self.records = records;
+    }
 
-                    let x = match prev {
-                        BlockType::Flat => x,
-                        BlockType::Uphill | BlockType::Downhill => x - 0.02,
-                    };
+    pub fn pepper_collected(&self, chunk: u32, block: u32) -> bool {
+        self.records
+            .get(&chunk)
+            .map_or(false, |record| record.peppers.contains(&block))
+    }
 
-                    (x, y)
-                }
+    pub fn enemy_killed(&self, chunk: u32, block: u32) -> bool {
+        self.records
+            .get(&chunk)
+            .map_or(false, |record| record.enemies.contains(&block))
+    }
+}
+
+/// Root of a chunk of terrain blocks
+#[derive(Component)]
+pub struct Level;
+
+#[derive(Component)]
+pub struct Chunk(pub u32);
+
+/// Despawned along with the chunk it was placed in
+#[derive(Component)]
+pub struct ChunkMember(pub u32);
+
+/// Sent when a chunk enters the world so things can be placed on it
+pub struct ChunkSpawnedEvent {
+    pub chunk: u32,
+    /// Index of the chunk's first block in the level
+    pub first_block: u32,
+    pub blocks: Vec<BlockType>,
+    /// Centre of every block's surface in the chunk, including gaps
+    pub surface: Vec<Vec2>,
+    /// Its peppers and enemies came from a snapshot, so none should be placed
+    pub restored: bool,
+}
+
+pub struct ChunkDespawnedEvent(pub u32);
+
+/// Sent when something falls far enough into a gap that it can't get out
+pub struct FellInPitEvent(pub Entity);
+
+/// Bottom of a gap
+#[derive(Component)]
+pub struct Pit;
+
+/// Hurts the player while touched
+#[derive(Component)]
+pub struct Spikes;
+
+/// Launches whatever touches it upwards
+#[derive(Component)]
+pub struct BouncePad;
+
+/// Seed that every random part of a level is derived from.
+///
+/// Can be set with `--seed <n>` on the command line, the `KAJAM_SEED`
+/// environment variable or the wasm `init` entry point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerrainSeed(pub u64);
+
+impl TerrainSeed {
+    pub const ENV_VAR: &'static str = "KAJAM_SEED";
+
+    /// Reads the seed from the command line or the environment
+    pub fn from_args() -> Option<Self> {
+        arg_value("--seed")
+            .or_else(|| std::env::var(Self::ENV_VAR).ok())
+            .and_then(|seed| seed.trim().parse().ok())
+            .map(TerrainSeed)
+    }
+
+    /// Creates an rng for one part of the level, `salt` keeps the parts independent
+    pub fn rng(&self, salt: u64) -> StdRng {
+        StdRng::seed_from_u64(self.mix(salt))
+    }
+
+    /// Creates an rng for one block or chunk of a part of the level
+    pub fn rng_at(&self, salt: u64, index: u32) -> StdRng {
+        StdRng::seed_from_u64(splitmix64(self.mix(salt) ^ index as u64))
+    }
+
+    /// Hashes the seed before the salt goes in, so neighbouring seeds and salts
+    /// don't share streams
+    fn mix(&self, salt: u64) -> u64 {
+        splitmix64(splitmix64(self.0) ^ salt)
+    }
+
+    fn noise_seed(&self) -> u32 {
+        (self.0 ^ (self.0 >> 32)) as u32
+    }
+}
+
+/// Scrambles every bit of `x` into every bit of the result
+fn splitmix64(x: u64) -> u64 {
+    let mut x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+impl Default for TerrainSeed {
+    fn default() -> Self {
+        let seed = Self::from_args().unwrap_or_else(|| TerrainSeed(rand::random()));
+        info!("terrain seed: {}", seed.0);
+
+        seed
+    }
+}
+
+fn generate_terrain(
+    mut commands: Commands,
+    mut terrain: ResMut<Terrain>,
+    seed: Res<TerrainSeed>,
+    config: Res<GameConfig>,
+    length: Res<LevelLength>,
+    level: Res<CurrentLevel>,
+    mut events: EventWriter<ChunkSpawnedEvent>,
+) {
+    let source = BlockSource::new(&seed, *length, &config, &level);
+    load_chunks_around(
+        &mut commands,
+        &mut terrain,
+        &source,
+        &config,
+        0.0,
+        &mut events,
+    );
+}
+
+fn restart_terrain(
+    mut commands: Commands,
+    mut terrain: ResMut<Terrain>,
+    seed: Res<TerrainSeed>,
+    config: Res<GameConfig>,
+    length: Res<LevelLength>,
+    level: Res<CurrentLevel>,
+    mut events: EventReader<RestartLevelEvent>,
+    mut spawned: EventWriter<ChunkSpawnedEvent>,
+    level_query: Query<Entity, Or<(With<Level>, With<ChunkMember>)>>,
+) {
+    if events.iter().last().is_none() {
+        return;
+    }
+
+    // Whatever was placed on the old chunks goes with them, the new chunks place their own
+    for entity in level_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    // The seed or level may have changed, so nothing generated so far can be reused,
+    // and everything collected or killed comes back, apart from the chunks a
+    // snapshot being loaded has marked
+    *terrain = Terrain {
+        restored: std::mem::take(&mut terrain.restored),
+        ..default()
+    };
+    let source = BlockSource::new(&seed, *length, &config, &level);
+    load_chunks_around(
+        &mut commands,
+        &mut terrain,
+        &source,
+        &config,
+        0.0,
+        &mut spawned,
+    );
+}
+
+fn stream_chunks(
+    mut commands: Commands,
+    mut terrain: ResMut<Terrain>,
+    seed: Res<TerrainSeed>,
+    config: Res<GameConfig>,
+    length: Res<LevelLength>,
+    level: Res<CurrentLevel>,
+    camera_query: Query<&Transform, With<MainCamera>>,
+    mut spawned: EventWriter<ChunkSpawnedEvent>,
+    mut despawned: EventWriter<ChunkDespawnedEvent>,
+) {
+    let x = match camera_query.get_single() {
+        Ok(camera) => camera.translation.x,
+        Err(_) => return,
+    };
+
+    let far: Vec<u32> = terrain
+        .loaded
+        .keys()
+        .copied()
+        .filter(|chunk| !terrain.in_range(*chunk, x))
+        .collect();
+
+    for chunk in far {
+        if let Some(entity) = terrain.loaded.remove(&chunk) {
+            commands.entity(entity).despawn_recursive();
+            despawned.send(ChunkDespawnedEvent(chunk));
+        }
+    }
+
+    let source = BlockSource::new(&seed, *length, &config, &level);
+    load_chunks_around(
+        &mut commands,
+        &mut terrain,
+        &source,
+        &config,
+        x,
+        &mut spawned,
+    );
+}
+
+/// Lets things jump up through platforms and land on them on the way down
+fn one_way_platforms(mut query: Query<(&Velocity, &mut CollisionLayers), With<Grounded>>) {
+    for (velocity, mut layers) in query.iter_mut() {
+        let rising = velocity.linear.y > 0.0;
+
+        if rising == layers.contains_mask(Layers::Platform) {
+            *layers = if rising {
+                layers.without_mask(Layers::Platform)
+            } else {
+                layers.with_mask(Layers::Platform)
+            };
+        }
+    }
+}
+
+fn despawn_chunk_members(
+    mut commands: Commands,
+    mut events: EventReader<ChunkDespawnedEvent>,
+    query: Query<(Entity, &ChunkMember)>,
+) {
+    for ev in events.iter() {
+        for (entity, member) in query.iter() {
+            if member.0 == ev.0 {
+                commands.entity(entity).despawn_recursive();
             }
-            _ => (x - 0.02, (y - new_y) + (new_y / 2.0)),
-        };
+        }
+    }
+}
 
-        x = x_offset;
+/// Left edge of a chunk, blocks are laid out `BLOCK_SIZE` apart with block 0 centred on 0
+fn chunk_x(chunk: u32) -> f32 {
+    (chunk * CHUNK_LEN) as f32 * BLOCK_SIZE - BLOCK_SIZE / 2.0
+}
 
-        let mut sprite = block.to_sprite();
-        sprite.sprite.color = color;
-        sprite.transform = sprite
-            .transform
-            .with_translation(Vec3::new(x_offset, y_offset, 900.0));
+/// Height of the ground at the left edge of each block and the right edge of the last one
+pub(crate) fn surface_heights(start: f32, blocks: &[(BlockType, f32)]) -> Vec<f32> {
+    let mut heights = Vec::with_capacity(blocks.len() + 1);
+    heights.push(start);
 
-        let entity = commands
-            .spawn_bundle(sprite)
+    for (_, rise) in blocks {
+        heights.push(heights[heights.len() - 1] + rise);
+    }
+
+    heights
+}
+
+impl Terrain {
+    /// Whether a chunk should be loaded with the camera at `x`
+    fn in_range(&self, chunk: u32, x: f32) -> bool {
+        if chunk as usize >= self.starts.len() {
+            return false;
+        }
+
+        let start = chunk_x(chunk);
+        let end = chunk_x(chunk + 1);
+
+        end >= x - STREAM_DISTANCE && start <= x + STREAM_DISTANCE
+    }
+
+    /// Generates chunk starts until they reach past `x`
+    fn generate_starts(&mut self, source: &BlockSource, x: f32) {
+        if self.starts.is_empty() {
+            self.starts.push(0.0);
+        }
+
+        loop {
+            let chunk = self.starts.len() as u32 - 1;
+
+            if chunk_x(chunk) > x + STREAM_DISTANCE
+                || source
+                    .length
+                    .chunks()
+                    .map_or(false, |chunks| chunk >= chunks)
+            {
+                break;
+            }
+
+            let rise: f32 = source.chunk(chunk).iter().map(|(_, rise)| rise).sum();
+
+            self.starts.push(self.starts[chunk as usize] + rise);
+        }
+    }
+}
+
+/// Where a level's blocks come from, its level file or the seed
+struct BlockSource<'a> {
+    seed: &'a TerrainSeed,
+    length: LevelLength,
+    reach: Reach,
+    file: Option<&'a LevelFile>,
+}
+
+impl<'a> BlockSource<'a> {
+    fn new(
+        seed: &'a TerrainSeed,
+        length: LevelLength,
+        config: &GameConfig,
+        level: &'a CurrentLevel,
+    ) -> Self {
+        let file = level.0.as_ref();
+
+        Self {
+            seed,
+            length: match file {
+                Some(file) => LevelLength::Blocks(file.blocks.len() as u32),
+                None => length,
+            },
+            reach: Reach::new(config),
+            file,
+        }
+    }
+
+    fn chunk(&self, chunk: u32) -> Vec<(BlockType, f32)> {
+        match self.file {
+            Some(file) => {
+                let first = (chunk * CHUNK_LEN) as usize;
+                let end = (first + CHUNK_LEN as usize).min(file.blocks.len());
+
+                file.blocks[first.min(end)..end]
+                    .iter()
+                    .map(|block| (*block, block.rise()))
+                    .collect()
+            }
+            None => chunk_blocks(self.seed, self.length, &self.reach, chunk),
+        }
+    }
+}
+
+fn load_chunks_around(
+    commands: &mut Commands,
+    terrain: &mut Terrain,
+    source: &BlockSource,
+    config: &GameConfig,
+    x: f32,
+    events: &mut EventWriter<ChunkSpawnedEvent>,
+) {
+    terrain.generate_starts(source, x);
+
+    let count = match source.length.chunks() {
+        Some(chunks) => chunks.min(terrain.starts.len() as u32),
+        None => terrain.starts.len() as u32,
+    };
+
+    for chunk in 0..count {
+        if terrain.is_loaded(chunk) || !terrain.in_range(chunk, x) {
+            continue;
+        }
+
+        let start = terrain.starts[chunk as usize];
+        let blocks = source.chunk(chunk);
+        let (entity, mut event) = spawn_chunk(commands, &config.terrain, chunk, start, blocks);
+        event.restored = terrain.restored.remove(&chunk);
+
+        terrain.loaded.insert(chunk, entity);
+        events.send(event);
+    }
+}
+
+fn spawn_chunk(
+    commands: &mut Commands,
+    config: &TerrainConfig,
+    chunk: u32,
+    start: f32,
+    blocks: Vec<(BlockType, f32)>,
+) -> (Entity, ChunkSpawnedEvent) {
+    let first = chunk * CHUNK_LEN;
+    let left = chunk_x(chunk);
+    let heights = surface_heights(start, &blocks);
+    let block_x = |i: usize| left + i as f32 * BLOCK_SIZE;
+
+    let mut children = Vec::new();
+
+    // Endless levels go round the colours again instead of running off the end
+    let block_color = |i: usize| {
+        let index = first + i as u32;
+        color_block((index % MAP_LEN) as usize, MAP_LEN as usize)
+    };
+    let run_color = |run: &Range<usize>| block_color((run.start + run.end) / 2);
+
+    let surface = blocks
+        .iter()
+        .enumerate()
+        .map(|(i, (_, rise))| Vec2::new(block_x(i) + BLOCK_SIZE / 2.0, heights[i] + rise / 2.0))
+        .collect();
+
+    // The surface strip takes each block's own colour, the body under it is one
+    // shape for each stretch of ground
+    for run in runs(&blocks, |block| block.is_solid()) {
+        for i in run.clone() {
+            children.push(spawn_shape(
+                commands,
+                ground_strip(block_x(i), &heights[i..=i + 1], config.surface_depth),
+                block_color(i),
+                900.0,
+            ));
+        }
+
+        children.push(spawn_shape(
+            commands,
+            ground_strip(
+                block_x(run.start),
+                &heights[run.start..=run.end],
+                config.ground_depth,
+            ),
+            dim(run_color(&run)),
+            899.0,
+        ));
+    }
+
+    // One collider for each stretch of ground so there are no seams between blocks to catch on
+    for run in runs(&blocks, |block| block.is_solid()) {
+        let width = run.len() as f32 * BLOCK_SIZE;
+
+        let collider = commands
+            .spawn()
+            .insert(Name::new("Ground"))
+            .insert(Transform::from_xyz(
+                block_x(run.start) + width / 2.0,
+                0.0,
+                0.0,
+            ))
+            .insert(GlobalTransform::default())
             .insert(RigidBody::Static)
-            .insert(CollisionShape::Cuboid {
-                half_extends: Vec3::new(BLOCK_SIZE / 2.0, BLOCK_HEIGHT / 2.0, 0.0),
-                border_radius: None,
+            .insert(CollisionShape::HeightField {
+                size: Vec2::new(width, 1.0),
+                heights: vec![heights[run.start..=run.end].to_vec()],
             })
             .insert(PhysicMaterial {
                 friction: 2.0 * BLOCK_SIZE,
@@ -76,53 +594,388 @@ fn generate_terrain(mut commands: Commands, mut terrain: ResMut<Terrain>) {
             .insert(
                 CollisionLayers::none()
                     .with_group(Layers::Level)
-                    .with_masks(&[Layers::Player, Layers::Enemy, Layers::Pepper]),
+                    .with_masks(&[
+                        Layers::Player,
+                        Layers::Enemy,
+                        Layers::Pepper,
+                        Layers::EnemyFireball,
+                    ]),
             )
             .id();
+        children.push(collider);
+    }
 
-        terrain.0.push(entity)
+    // Whatever falls this far into a gap can't get back out
+    for run in runs(&blocks, |block| !block.is_solid()) {
+        let y = heights[run.start] - PIT_DEPTH - PIT_DEPTH / 2.0;
+        children.push(spawn_trigger(
+            commands,
+            block_x(run.start),
+            run.len(),
+            y,
+            PIT_DEPTH,
+            Pit,
+        ));
     }
 
-    commands
+    for run in runs(&blocks, |block| block == BlockType::Platform) {
+        let width = run.len() as f32 * BLOCK_SIZE;
+        let y = heights[run.start] + PLATFORM_HEIGHT - PLATFORM_THICKNESS / 2.0;
+
+        let platform = commands
+            .spawn()
+            .insert(Name::new("Platform"))
+            .insert(Transform::from_xyz(
+                block_x(run.start) + width / 2.0,
+                y,
+                0.0,
+            ))
+            .insert(GlobalTransform::default())
+            .insert(RigidBody::Static)
+            .insert(CollisionShape::Cuboid {
+                half_extends: Vec3::new(width / 2.0, PLATFORM_THICKNESS / 2.0, 0.0),
+                border_radius: None,
+            })
+            .insert(
+                CollisionLayers::none()
+                    .with_group(Layers::Platform)
+                    .with_masks(&[Layers::Player, Layers::Enemy]),
+            )
+            .id();
+        children.push(platform);
+
+        let top = heights[run.start] + PLATFORM_HEIGHT;
+        children.push(spawn_shape(
+            commands,
+            ground_strip(
+                block_x(run.start),
+                &vec![top; run.len() + 1],
+                PLATFORM_THICKNESS,
+            ),
+            run_color(&run),
+            901.0,
+        ));
+    }
+
+    for run in runs(&blocks, |block| block == BlockType::Spikes) {
+        let y = heights[run.start] + SPIKE_HEIGHT / 2.0;
+        children.push(spawn_trigger(
+            commands,
+            block_x(run.start),
+            run.len(),
+            y,
+            SPIKE_HEIGHT,
+            Spikes,
+        ));
+        children.push(spawn_shape(
+            commands,
+            spike_row(block_x(run.start), heights[run.start], run.len()),
+            Color::SILVER,
+            901.0,
+        ));
+    }
+
+    for run in runs(&blocks, |block| block == BlockType::Bounce) {
+        let y = heights[run.start] + PAD_HEIGHT;
+        children.push(spawn_trigger(
+            commands,
+            block_x(run.start),
+            run.len(),
+            y,
+            PAD_HEIGHT,
+            BouncePad,
+        ));
+        children.push(spawn_shape(
+            commands,
+            ground_strip(block_x(run.start), &vec![y; run.len() + 1], PAD_HEIGHT),
+            Color::YELLOW,
+            901.0,
+        ));
+    }
+
+    let entity = commands
         .spawn()
-        .insert(Name::new("Level"))
+        .insert(Name::new(format!("Chunk {}", chunk)))
+        .insert(Level)
+        .insert(Chunk(chunk))
         .insert(Transform::default())
         .insert(GlobalTransform::default())
-        .push_children(&terrain.0);
+        .push_children(&children)
+        .id();
+
+    let event = ChunkSpawnedEvent {
+        chunk,
+        first_block: first,
+        blocks: blocks.iter().map(|(block, _)| *block).collect(),
+        surface,
+        restored: false,
+    };
+
+    (entity, event)
+}
+
+/// Ranges of consecutive blocks that `filter` holds for
+pub(crate) fn runs(
+    blocks: &[(BlockType, f32)],
+    filter: impl Fn(BlockType) -> bool,
+) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+
+    for (i, (block, _)) in blocks.iter().enumerate() {
+        if !filter(*block) {
+            continue;
+        }
+
+        match runs.last_mut() {
+            Some(run) if run.end == i => run.end = i + 1,
+            _ => runs.push(i..i + 1),
+        }
+    }
+
+    runs
 }
 
-fn process_blocks(blocks: Vec<BlockType>) -> Vec<(BlockType, f32)> {
-    blocks
+/// A sensor over `blocks` blocks starting at `x`, centred on `y`
+fn spawn_trigger(
+    commands: &mut Commands,
+    x: f32,
+    blocks: usize,
+    y: f32,
+    height: f32,
+    marker: impl Component,
+) -> Entity {
+    let width = blocks as f32 * BLOCK_SIZE;
+
+    commands
+        .spawn()
+        .insert(Transform::from_xyz(x + width / 2.0, y, 0.0))
+        .insert(GlobalTransform::default())
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Cuboid {
+            half_extends: Vec3::new(width / 2.0, height / 2.0, 0.0),
+            border_radius: None,
+        })
+        .insert(
+            CollisionLayers::none()
+                .with_group(Layers::Trigger)
+                .with_masks(&[Layers::Player, Layers::Enemy]),
+        )
+        .insert(marker)
+        .id()
+}
+
+/// The ground under blocks starting at `x`, from the surface `heights` at
+/// each block edge down to `depth` below them
+fn ground_strip(x: f32, heights: &[f32], depth: f32) -> shapes::Polygon {
+    let top = heights
         .iter()
         .enumerate()
-        .map(|(i, block)| {
-            if i == 0 {
-                (BlockType::Flat, 0.0)
-            } else {
-                process_block(*block, blocks[i - 1])
+        .map(|(i, y)| Vec2::new(x + i as f32 * BLOCK_SIZE, *y));
+    let bottom = top.clone().rev().map(|point| point - Vec2::Y * depth);
+
+    shapes::Polygon {
+        points: top.chain(bottom).collect(),
+        closed: true,
+    }
+}
+
+/// A spike on each of `blocks` flat blocks starting at `x`, standing on `y`
+fn spike_row(x: f32, y: f32, blocks: usize) -> shapes::Polygon {
+    let mut points = vec![Vec2::new(x, y)];
+
+    for i in 0..blocks {
+        let left = x + i as f32 * BLOCK_SIZE;
+        points.push(Vec2::new(left + BLOCK_SIZE / 2.0, y + SPIKE_HEIGHT));
+        points.push(Vec2::new(left + BLOCK_SIZE, y));
+    }
+
+    shapes::Polygon {
+        points,
+        closed: true,
+    }
+}
+
+fn spawn_shape(commands: &mut Commands, shape: shapes::Polygon, color: Color, z: f32) -> Entity {
+    commands
+        .spawn_bundle(GeometryBuilder::build_as(
+            &shape,
+            DrawMode::Fill(FillMode::color(color)),
+            Transform::from_xyz(0.0, 0.0, z),
+        ))
+        .id()
+}
+
+fn chunk_blocks(
+    seed: &TerrainSeed,
+    length: LevelLength,
+    reach: &Reach,
+    chunk: u32,
+) -> Vec<(BlockType, f32)> {
+    let first = chunk * CHUNK_LEN;
+    let end = match length.blocks() {
+        Some(blocks) => (first + CHUNK_LEN).min(blocks),
+        None => first + CHUNK_LEN,
+    };
+
+    generate_blocks_between(seed, length, reach, first, end)
+}
+
+/// Generates the processed block sequence for a seed
+pub fn generate_blocks(seed: &TerrainSeed, length: u32, reach: &Reach) -> Vec<(BlockType, f32)> {
+    generate_blocks_between(seed, LevelLength::Blocks(length), reach, 0, length)
+}
+
+/// Generates blocks `start..end` of a level, the same as the matching part of
+/// the whole level so chunks line up
+pub fn generate_blocks_between(
+    seed: &TerrainSeed,
+    length: LevelLength,
+    reach: &Reach,
+    start: u32,
+    end: u32,
+) -> Vec<(BlockType, f32)> {
+    // Each block depends on the two before it
+    let from = start.saturating_sub(2);
+    let heights = generate_heights(seed, from, end);
+    let height = |i: u32| heights[(i - from) as usize];
+
+    let raw_block = |i: u32| {
+        if i == 0 || length.blocks() == Some(i + 1) {
+            BlockType::Flat
+        } else {
+            height_to_block(height(i) - height(i - 1))
+        }
+    };
+
+    let sections: HashMap<u32, Vec<BlockType>> = (start / FEATURE_SPACING
+        ..=end.saturating_sub(1) / FEATURE_SPACING)
+        .filter_map(|segment| feature_section(seed, length, reach, segment).map(|s| (segment, s)))
+        .collect();
+
+    (start..end)
+        .map(|i| {
+            let section = sections.get(&(i / FEATURE_SPACING));
+
+            match section {
+                Some(section) => {
+                    let block = section[(i % FEATURE_SPACING) as usize];
+                    (block, block.rise())
+                }
+                None if i == 0 => (BlockType::Flat, 0.0),
+                None => process_block(raw_block(i), raw_block(i - 1), reach),
             }
         })
         .collect()
 }
 
-fn process_block(mut block: BlockType, prev: BlockType) -> (BlockType, f32) {
-    block = match block {
-        BlockType::Flat => block,
-        BlockType::Uphill | BlockType::Downhill => match prev {
-            BlockType::Flat => block,
-            BlockType::Uphill | BlockType::Downhill => BlockType::Flat,
-        },
-    };
+/// Blocks of a `FEATURE_SPACING` stretch of the level, if it has a feature.
+/// The rest of the stretch is left flat so there is room to run up and land.
+fn feature_section(
+    seed: &TerrainSeed,
+    length: LevelLength,
+    reach: &Reach,
+    segment: u32,
+) -> Option<Vec<BlockType>> {
+    match roll_feature(seed, length, reach, segment)? {
+        Feature::Section(section) => Some(section),
+        Feature::Impassable => {
+            warn!(
+                "seed {}: no feature at block {} can be got past, leaving it flat",
+                seed.0,
+                segment * FEATURE_SPACING
+            );
+            Some(vec![BlockType::Flat; FEATURE_SPACING as usize])
+        }
+    }
+}
+
+/// What a `FEATURE_SPACING` stretch of the level rolled for its feature
+enum Feature {
+    Section(Vec<BlockType>),
+    /// None of the `FEATURE_ATTEMPTS` rolls could be got past
+    Impassable,
+}
 
-    let angled_offset = BLOCK_SIZE * 45.0_f32.to_radians().sin();
+fn roll_feature(
+    seed: &TerrainSeed,
+    length: LevelLength,
+    reach: &Reach,
+    segment: u32,
+) -> Option<Feature> {
+    let first = segment * FEATURE_SPACING;
 
-    let y = match block {
-        BlockType::Uphill => angled_offset - 0.005,
-        BlockType::Downhill => -angled_offset + 0.005,
-        BlockType::Flat => 0.0,
+    // Keep the start clear and the last block flat
+    let fits = length
+        .blocks()
+        .map_or(true, |blocks| first + FEATURE_SPACING < blocks);
+    if first < FEATURE_START || !fits {
+        return None;
+    }
+
+    let mut rng = seed.rng_at(FEATURE_SALT, segment);
+
+    if !rng.gen_bool(FEATURE_CHANCE) {
+        return None;
+    }
+
+    // Features the player can't get past are rerolled
+    for _ in 0..FEATURE_ATTEMPTS {
+        let feature = match rng.gen_range(0..4) {
+            0 => vec![BlockType::Gap; rng.gen_range(1..=MAX_GAP)],
+            1 => vec![
+                BlockType::Gap,
+                BlockType::Platform,
+                BlockType::Platform,
+                BlockType::Platform,
+                BlockType::Gap,
+            ],
+            2 => vec![BlockType::Spikes; rng.gen_range(1..=MAX_SPIKES)],
+            _ => vec![BlockType::Bounce],
+        };
+
+        let mut section = vec![BlockType::Flat; FEATURE_SPACING as usize];
+        section[FEATURE_OFFSET..FEATURE_OFFSET + feature.len()].copy_from_slice(&feature);
+
+        let blocks: Vec<_> = section.iter().map(|block| (*block, block.rise())).collect();
+        if traversal::validate(&blocks, reach).is_empty() {
+            return Some(Feature::Section(section));
+        }
+    }
+
+    Some(Feature::Impassable)
+}
+
+/// First blocks of the stretches of a level that are left flat because none
+/// of the features rolled for them could be got past
+pub fn flattened_features(seed: &TerrainSeed, length: u32, reach: &Reach) -> Vec<u32> {
+    (0..length / FEATURE_SPACING)
+        .filter(|segment| {
+            matches!(
+                roll_feature(seed, LevelLength::Blocks(length), reach, *segment),
+                Some(Feature::Impassable)
+            )
+        })
+        .map(|segment| segment * FEATURE_SPACING)
+        .collect()
+}
+
+fn process_block(block: BlockType, prev: BlockType, reach: &Reach) -> (BlockType, f32) {
+    // A slope straight into one going the other way makes a notch or peak that catches on things
+    let mut block = if block.slope() * prev.slope() < 0 {
+        BlockType::Flat
+    } else {
+        block
     };
 
-    (block, y)
+    // Ease off slopes too steep for the player to walk up
+    if block == BlockType::Uphill && !reach.can_climb(block) {
+        block = BlockType::GentleUphill;
+    }
+    if !reach.can_climb(block) {
+        block = BlockType::Flat;
+    }
+
+    (block, block.rise())
 }
 
 fn color_block(i: usize, len: usize) -> Color {
@@ -143,57 +996,151 @@ fn color_block(i: usize, len: usize) -> Color {
     }
 }
 
-fn heightmap_to_blocks(heights: Vec<f32>) -> Vec<BlockType> {
-    heights
-        .iter()
-        .enumerate()
-        .map(|(index, height)| {
-            if (index == 0) || (index == heights.len() - 1) {
-                return BlockType::Flat;
-            }
+/// Colour of the ground body under a surface of `color`
+fn dim(color: Color) -> Color {
+    Color::rgb(color.r() * 0.35, color.g() * 0.35, color.b() * 0.35)
+}
 
-            let prev = &heights[index - 1];
-            let delta = height - prev;
+fn height_to_block(delta: f32) -> BlockType {
+    if delta > 0.8 {
+        BlockType::Uphill
+    } else if delta > 0.3 {
+        BlockType::GentleUphill
+    } else if delta < -0.9 {
+        BlockType::Downhill
+    } else if delta < -0.4 {
+        BlockType::GentleDownhill
+    } else {
+        BlockType::Flat
+    }
+}
 
-            if delta > 0.3 {
-                return BlockType::Uphill;
-            } else if delta < -0.4 {
-                return BlockType::Downhill;
-            } else {
-                return BlockType::Flat;
-            }
+fn generate_heights(seed: &TerrainSeed, start: u32, end: u32) -> Vec<f32> {
+    let noise_fn = noise::SuperSimplex::new().set_seed(seed.noise_seed());
+
+    (start..end)
+        .map(|i| {
+            // Seeded per block so any part of the level can be generated on its own
+            let mut rng = seed.rng_at(HEIGHT_SALT, i);
+            let val = (i as f64).sin();
+            let height = noise_fn.get([i as f64 * 0.1, val]);
+
+            (if rng.gen_bool(0.5) { 1.0 } else { -1.0 }) * height as f32 + rng.gen::<f32>()
         })
         .collect()
 }
 
-fn generate_heightmap(length: u32) -> Vec<f32> {
-    debug!("generating terrain with a length of {}", length);
-    let mut heights = Vec::new();
-    let noise_fn = noise::SuperSimplex::new();
-    let rand = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_micros();
-
-    for i in 0..length {
-        let val = (i as f64).sin();
-        let height = noise_fn.get([
-            (rand as f64 / val),
-            ((val.tan() as u32) >> (val.cosh() as u32)) as f64,
-            (rand ^ (val as u128)) as f64,
-        ]);
-
-        heights.push(
-            (if rand::thread_rng().gen_bool(0.5) {
-                1.0
-            } else {
-                -1.0
-            }) * height as f32
-                + rand::random::<f32>(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{enemy::ENEMY_SALT, pepper::PEPPER_SALT};
+
+    fn reach() -> Reach {
+        Reach::new(&GameConfig::default())
+    }
+
+    #[test]
+    fn same_seed_generates_same_blocks() {
+        let seed = TerrainSeed(42);
+
+        assert_eq!(
+            generate_heights(&seed, 0, MAP_LEN),
+            generate_heights(&seed, 0, MAP_LEN)
+        );
+        assert_eq!(
+            generate_blocks(&seed, MAP_LEN, &reach()),
+            generate_blocks(&seed, MAP_LEN, &reach())
         );
     }
 
-    debug!("generatied terrain");
+    #[test]
+    fn rng_streams_do_not_overlap() {
+        let draws = |seed: u64, salt: u64, index: u32| -> Vec<u64> {
+            let mut rng = TerrainSeed(seed).rng_at(salt, index);
+            (0..4).map(|_| rng.gen()).collect()
+        };
+
+        let salts = [HEIGHT_SALT, FEATURE_SALT, PEPPER_SALT, ENEMY_SALT];
+        let mut streams = HashSet::new();
 
-    heights
+        // Neighbouring seeds are the next levels, and used to share streams with a
+        // different salt or index
+        for seed in 0..4 {
+            for salt in salts {
+                for index in 0..4 {
+                    assert!(streams.insert(draws(seed, salt, index)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn different_seeds_generate_different_blocks() {
+        assert_ne!(
+            generate_blocks(&TerrainSeed(1), MAP_LEN, &reach()),
+            generate_blocks(&TerrainSeed(2), MAP_LEN, &reach())
+        );
+    }
+
+    #[test]
+    fn chunks_match_the_whole_level() {
+        let seed = TerrainSeed(7);
+        let length = LevelLength::Blocks(MAP_LEN);
+        let whole = generate_blocks(&seed, MAP_LEN, &reach());
+
+        let chunked: Vec<_> = (0..length.chunks().unwrap())
+            .flat_map(|chunk| chunk_blocks(&seed, length, &reach(), chunk))
+            .collect();
+
+        assert_eq!(chunked, whole);
+    }
+
+    #[test]
+    fn chunk_surfaces_join_up() {
+        let seed = TerrainSeed(7);
+        let length = LevelLength::Blocks(MAP_LEN);
+        let config = GameConfig::default();
+        let level = CurrentLevel::default();
+        let mut terrain = Terrain::default();
+        terrain.generate_starts(
+            &BlockSource::new(&seed, length, &config, &level),
+            MAP_LEN as f32 * BLOCK_SIZE,
+        );
+
+        let whole = surface_heights(0.0, &generate_blocks(&seed, MAP_LEN, &reach()));
+
+        for chunk in 0..length.chunks().unwrap() {
+            let blocks = chunk_blocks(&seed, length, &reach(), chunk);
+            let heights = surface_heights(terrain.starts[chunk as usize], &blocks);
+            let first = (chunk * CHUNK_LEN) as usize;
+
+            assert_eq!(heights.len(), blocks.len() + 1);
+            for (i, height) in heights.iter().enumerate() {
+                assert!((height - whole[first + i]).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn features_leave_the_level_traversable() {
+        for seed in 0..50 {
+            let blocks = generate_blocks(&TerrainSeed(seed), MAP_LEN, &reach());
+            let types: Vec<_> = blocks.iter().map(|(block, _)| *block).collect();
+
+            assert!(types[..FEATURE_START as usize]
+                .iter()
+                .all(|block| block.is_walkable()));
+            assert_eq!(types[types.len() - 1], BlockType::Flat);
+
+            for pair in types.windows(2) {
+                assert!(pair[0].slope() * pair[1].slope() >= 0, "{:?}", pair);
+            }
+
+            for run in runs(&blocks, |block| !block.is_solid()) {
+                assert!(run.len() <= 5);
+                assert_eq!(types[run.start - 1], BlockType::Flat);
+                assert_eq!(types[run.end], BlockType::Flat);
+            }
+        }
+    }
 }
@@ -0,0 +1,228 @@
+use std::fmt;
+
+use crate::{
+    block_type::{BlockType, BLOCK_SIZE},
+    config::GameConfig,
+    player::PLAYER_SIZE,
+    terrain::{runs, surface_heights, PLATFORM_HEIGHT},
+};
+
+/// How far the player can jump and how steep a slope they can walk up,
+/// worked out from their controller and gravity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reach {
+    pub speed: f32,
+    pub jump_speed: f32,
+    pub gravity: f32,
+    /// Steepest walkable slope in degrees
+    pub max_slope: f32,
+    /// Radius of the player, landings need the player's centre over the ground
+    pub radius: f32,
+}
+
+impl Reach {
+    pub fn new(config: &GameConfig) -> Self {
+        let controller = &config.player.controller;
+
+        Self {
+            speed: controller.max_speed,
+            jump_speed: controller.jump_speed,
+            gravity: -config.physics.gravity.y,
+            max_slope: controller.max_slope,
+            radius: PLAYER_SIZE,
+        }
+    }
+
+    /// Furthest the player can travel sideways during a jump that lands `rise` higher
+    /// than it started, `None` if the jump can't get that high
+    pub fn distance(&self, rise: f32) -> Option<f32> {
+        if self.gravity <= 0.0 {
+            return Some(f32::INFINITY);
+        }
+
+        let discriminant = self.jump_speed * self.jump_speed - 2.0 * self.gravity * rise;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        // Time until the jump comes back down through `rise` on its way down
+        let time = (self.jump_speed + discriminant.sqrt()) / self.gravity;
+        Some(self.speed * time)
+    }
+
+    /// Whether a jump can cross `gap` and land `rise` higher
+    pub fn can_jump(&self, gap: f32, rise: f32) -> bool {
+        self.distance(rise)
+            .map_or(false, |distance| distance >= gap + self.radius)
+    }
+
+    /// Whether a block can be walked up
+    pub fn can_climb(&self, block: BlockType) -> bool {
+        block.rise().atan2(BLOCK_SIZE).to_degrees() <= self.max_slope
+    }
+}
+
+/// A part of a level the player can't get past
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Problem {
+    /// Index of the first block of the section in the blocks that were checked
+    pub block: usize,
+    pub kind: ProblemKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProblemKind {
+    /// A slope steeper than the player can walk up
+    TooSteep,
+    /// Ground or a platform that can't be jumped to from anything before it
+    Unreachable { gap: f32, rise: f32 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ProblemKind::TooSteep => write!(f, "block {} is too steep to climb", self.block),
+            ProblemKind::Unreachable { gap, rise } => write!(
+                f,
+                "block {} can't be reached, {:.2} away and {:.2} higher",
+                self.block, gap, rise
+            ),
+        }
+    }
+}
+
+/// Something that can be stood on, in block widths and world heights
+#[derive(Debug, Clone, Copy)]
+struct Surface {
+    start: usize,
+    end: usize,
+    start_height: f32,
+    end_height: f32,
+}
+
+/// Finds every section of `blocks` the player can't get past going right
+pub fn validate(blocks: &[(BlockType, f32)], reach: &Reach) -> Vec<Problem> {
+    let heights = surface_heights(0.0, blocks);
+    let mut problems = Vec::new();
+
+    for (i, (block, _)) in blocks.iter().enumerate() {
+        if block.is_solid() && !reach.can_climb(*block) {
+            problems.push(Problem {
+                block: i,
+                kind: ProblemKind::TooSteep,
+            });
+        }
+    }
+
+    let ground = runs(blocks, |block| block.is_solid())
+        .into_iter()
+        .map(|run| Surface {
+            start: run.start,
+            end: run.end,
+            start_height: heights[run.start],
+            end_height: heights[run.end],
+        });
+    let platforms = runs(blocks, |block| block == BlockType::Platform)
+        .into_iter()
+        .map(|run| Surface {
+            start: run.start,
+            end: run.end,
+            start_height: heights[run.start] + PLATFORM_HEIGHT,
+            end_height: heights[run.end] + PLATFORM_HEIGHT,
+        });
+
+    let mut surfaces: Vec<Surface> = ground.chain(platforms).collect();
+    surfaces.sort_by_key(|surface| surface.start);
+
+    // Only ground can be started from, a level starting over a gap is already lost
+    let mut reachable = vec![false; surfaces.len()];
+    for (i, surface) in surfaces.iter().enumerate() {
+        let mut closest = None;
+
+        reachable[i] = i == 0 && surface.start == 0;
+
+        for (from, before) in surfaces[..i].iter().enumerate() {
+            if !reachable[from] {
+                continue;
+            }
+
+            let gap = surface.start.saturating_sub(before.end) as f32 * BLOCK_SIZE;
+            let rise = surface.start_height - before.end_height;
+
+            if reach.can_jump(gap, rise) {
+                reachable[i] = true;
+                break;
+            }
+
+            closest = Some(ProblemKind::Unreachable { gap, rise });
+        }
+
+        if !reachable[i] {
+            problems.push(Problem {
+                block: surface.start,
+                kind: closest.unwrap_or(ProblemKind::Unreachable {
+                    gap: 0.0,
+                    rise: 0.0,
+                }),
+            });
+        }
+    }
+
+    problems.sort_by_key(|problem| problem.block);
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reach() -> Reach {
+        Reach {
+            speed: 1.0,
+            jump_speed: 2.0,
+            gravity: 4.0,
+            max_slope: 30.0,
+            radius: 0.05,
+        }
+    }
+
+    #[test]
+    fn jumps_are_limited_by_height_and_distance() {
+        let reach = reach();
+
+        // Up and back down takes a second at this speed and gravity
+        assert_eq!(reach.distance(0.0), Some(1.0));
+        assert!(reach.can_jump(0.9, 0.0));
+        assert!(!reach.can_jump(1.0, 0.0));
+        assert!(reach.distance(0.6).is_none());
+    }
+
+    #[test]
+    fn validate_finds_wide_gaps_and_steep_slopes() {
+        let reach = reach();
+        let level = |blocks: &[BlockType]| -> Vec<(BlockType, f32)> {
+            blocks.iter().map(|block| (*block, block.rise())).collect()
+        };
+
+        let mut wide = vec![BlockType::Flat; 3];
+        wide.extend(vec![BlockType::Gap; 12]);
+        wide.extend(vec![BlockType::Flat; 3]);
+
+        assert!(validate(
+            &level(&[BlockType::Flat, BlockType::Gap, BlockType::Flat]),
+            &reach
+        )
+        .is_empty());
+        assert_eq!(
+            validate(&level(&wide), &reach)
+                .iter()
+                .map(|problem| problem.block)
+                .collect::<Vec<_>>(),
+            vec![15]
+        );
+        assert_eq!(
+            validate(&level(&[BlockType::Flat, BlockType::Uphill]), &reach)[0].kind,
+            ProblemKind::TooSteep
+        );
+    }
+}
@@ -14,6 +14,28 @@ pub fn inv_lerp(x: f32, a: f32, b: f32) -> f32 {
     (x - a) / (b - a)
 }
 
+/// Finds the value of `--name <value>` or `--name=<value>` on the command line
+pub fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    let prefix = format!("{}=", name);
+    let mut value = None;
+
+    while let Some(arg) = args.next() {
+        if arg == name {
+            value = args.next();
+        } else if let Some(rest) = arg.strip_prefix(&prefix) {
+            value = Some(rest.to_string());
+        }
+    }
+
+    value
+}
+
+/// Whether `--name` was passed on the command line
+pub fn has_arg(name: &str) -> bool {
+    std::env::args().skip(1).any(|arg| arg == name)
+}
+
 #[allow(dead_code)]
 pub fn get_components_for_entity<'a>(
     entity: &Entity,
@@ -34,4 +56,9 @@ pub enum Layers {
     Pepper,
     Enemy,
     Level,
+    EnemyFireball,
+    /// One-way platforms, only collided with on the way down
+    Platform,
+    /// Sensors in the level like spikes, pits and bounce pads
+    Trigger,
 }
@@ -0,0 +1,492 @@
+use bevy::{
+    asset::Assets,
+    ecs::event::Events,
+    prelude::{Entity, Name, Transform, Vec3},
+};
+use kajam_lib::{
+    block_type::{BlockType, BLOCK_SIZE},
+    checkpoint::{Checkpoint, CheckpointPost},
+    config::GameConfig,
+    controller::CharacterController,
+    enemy::{Enemy, EnemyBrain},
+    fireball::{Faction, Fireball, ENEMY_FIREBALL_RADIUS},
+    goal::Goal,
+    headless::Simulation,
+    health::{DamageKind, Health},
+    level::LevelNumber,
+    level_file::{EnemySpawn, LevelFile},
+    pepper::{Pepper, PepperCollectedEvent},
+    player::{Lives, Player},
+    save::{SaveData, SaveStorage},
+    snapshot::Snapshot,
+    state::AppState,
+    stats::RunStats,
+    terrain::{ChunkMember, TerrainSeed, CHUNK_LEN, MAP_LEN},
+};
+
+#[test]
+fn level_spawns_enemies_and_peppers() {
+    let mut sim = Simulation::new(1);
+
+    let chunks = sim.chunks().len();
+
+    assert!(chunks > 0);
+    assert!(sim.enemies() > 0);
+    assert_eq!(sim.peppers(), chunks * (CHUNK_LEN / 10) as usize);
+    assert_eq!(sim.fireballs(), 0);
+}
+
+#[test]
+fn level_file_places_the_player_peppers_and_enemies() {
+    let level: LevelFile =
+        ron::de::from_str(include_str!("../assets/levels/tutorial.level.ron")).unwrap();
+    let spawn = level.surface(level.spawn);
+
+    let mut sim = Simulation::with_level(level.clone());
+
+    // Only the chunks around the player are loaded
+    let loaded = sim.chunks().len() as u32 * CHUNK_LEN;
+    let peppers = level.peppers.iter().filter(|b| **b < loaded).count();
+    let enemies = level.enemies.iter().filter(|e| e.block < loaded).count();
+
+    assert!(peppers > 0 && enemies > 0);
+    assert_eq!(sim.peppers(), peppers);
+    assert_eq!(sim.enemies(), enemies);
+    assert!((sim.player().translation.x - spawn.x).abs() < 0.01);
+}
+
+#[test]
+fn player_lands_on_terrain() {
+    let mut sim = Simulation::new(1);
+    let start = sim.player();
+
+    sim.step(180);
+
+    let player = sim.player();
+    assert!(player.translation.y > -1.5, "player fell through the level");
+    assert_eq!(player.health, start.health);
+}
+
+#[test]
+fn restart_respawns_the_level() {
+    let mut sim = Simulation::new(1);
+    let enemies = sim.enemies();
+    let peppers = sim.peppers();
+
+    sim.step(30);
+    sim.restart();
+    sim.step(2);
+
+    assert_eq!(sim.enemies(), enemies);
+    assert_eq!(sim.peppers(), peppers);
+    assert_eq!(sim.player().peppers, 0);
+}
+
+#[test]
+fn damage_hurts_the_player() {
+    let mut sim = Simulation::new(1);
+    let player = sim.entities::<Player>()[0];
+    let start = sim.player().health;
+
+    sim.hurt(player, 10.0, DamageKind::Contact);
+    sim.step(1);
+
+    assert_eq!(sim.player().health, start - 10.0);
+}
+
+#[test]
+fn player_is_invulnerable_right_after_a_hit() {
+    let mut sim = Simulation::new(1);
+    let player = sim.entities::<Player>()[0];
+    let start = sim.player().health;
+
+    for _ in 0..2 {
+        sim.hurt(player, 10.0, DamageKind::Contact);
+        sim.step(1);
+    }
+
+    assert_eq!(sim.player().health, start - 10.0);
+}
+
+#[test]
+fn lethal_damage_despawns_an_enemy() {
+    let mut sim = Simulation::new(1);
+    let enemies = sim.enemies();
+    let enemy = sim.entities::<Enemy>()[0];
+
+    sim.hurt(enemy, 10_000.0, DamageKind::Fireball);
+    sim.step(2);
+
+    assert_eq!(sim.enemies(), enemies - 1);
+}
+
+#[test]
+fn config_edits_reach_enemies_already_in_the_world() {
+    let mut sim = Simulation::new(1);
+    let enemy = sim.entities::<Enemy>()[0];
+    let speed = sim
+        .world()
+        .get::<CharacterController>(enemy)
+        .unwrap()
+        .max_speed;
+
+    // Edits made before the config file loads would be overwritten by it
+    while sim.world().resource::<Assets<GameConfig>>().is_empty() {
+        sim.step(1);
+    }
+    sim.step(2);
+
+    {
+        let mut config = sim.world().resource_mut::<GameConfig>();
+        config.enemy.controller.acceleration = 123.0;
+        config.enemy.patrol_range = 4.0;
+    }
+    sim.step(1);
+
+    let controller = sim.world().get::<CharacterController>(enemy).unwrap();
+    assert_eq!(controller.acceleration, 123.0);
+    // Still the kind's own speed
+    assert_eq!(controller.max_speed, speed);
+
+    let (left, right) = sim.world().get::<EnemyBrain>(enemy).unwrap().patrol;
+    assert!((right - left - 8.0).abs() < 1e-4);
+}
+
+#[test]
+fn chunks_stream_in_and_out_around_the_player() {
+    let mut sim = Simulation::new(1);
+    assert_eq!(sim.chunks()[0], 0);
+
+    sim.teleport(Vec3::new(30.0, 100.0, 999.0));
+    sim.step(3);
+
+    let chunks = sim.chunks();
+    assert!(!chunks.contains(&0), "chunk behind the player was kept");
+    assert!(chunks.contains(&6), "chunk under the player was not loaded");
+}
+
+#[test]
+fn collected_peppers_and_killed_enemies_stay_gone_when_chunks_stream_back_in() {
+    let mut sim = Simulation::new(1);
+    let start = sim.player().translation;
+    let enemies = sim.enemies();
+    let peppers = sim.peppers();
+
+    let enemy = sim.entities::<Enemy>()[0];
+    sim.hurt(enemy, 10_000.0, DamageKind::Fireball);
+
+    let pepper = sim.entities::<Pepper>()[0];
+    let chunk = sim.world().get::<ChunkMember>(pepper).unwrap().0;
+    let block = sim.world().get::<Pepper>(pepper).unwrap().block;
+    sim.world().despawn(pepper);
+    sim.world()
+        .resource_mut::<Events<PepperCollectedEvent>>()
+        .send(PepperCollectedEvent { chunk, block });
+    sim.step(2);
+
+    sim.teleport(Vec3::new(30.0, 100.0, 999.0));
+    sim.step(3);
+    assert!(!sim.chunks().contains(&chunk));
+
+    sim.teleport(start);
+    sim.step(3);
+
+    assert_eq!(sim.enemies(), enemies - 1);
+    assert_eq!(sim.peppers(), peppers - 1);
+
+    sim.restart();
+    sim.step(2);
+
+    assert_eq!(sim.enemies(), enemies);
+    assert_eq!(sim.peppers(), peppers);
+}
+
+#[test]
+fn finite_levels_end_and_endless_ones_do_not() {
+    let last_chunk = MAP_LEN / CHUNK_LEN - 1;
+    let far = Vec3::new(MAP_LEN as f32 * 0.1 + 20.0, 100.0, 999.0);
+
+    let mut finite = Simulation::new(1);
+    finite.teleport(far);
+    finite.step(3);
+    assert!(finite.chunks().iter().all(|chunk| *chunk <= last_chunk));
+
+    let mut endless = Simulation::endless(1);
+    endless.teleport(far);
+    endless.step(3);
+    assert!(endless.chunks().iter().any(|chunk| *chunk > last_chunk));
+}
+
+#[test]
+fn landing_on_a_platform_keeps_the_other_collisions_of_the_frame() {
+    let mut sim = Simulation::new(1);
+    let player = sim.entities::<Player>()[0];
+
+    let mut platform = None;
+    for chunk in 0..MAP_LEN / CHUNK_LEN {
+        sim.teleport(Vec3::new(
+            (chunk * CHUNK_LEN) as f32 * BLOCK_SIZE,
+            2.0,
+            999.0,
+        ));
+        sim.step(1);
+
+        platform = sim
+            .world()
+            .query::<(Entity, &Name, &Transform)>()
+            .iter(sim.world())
+            .find(|(_, name, _)| name.as_str() == "Platform")
+            .map(|(entity, _, transform)| (entity, transform.translation));
+
+        if platform.is_some() {
+            break;
+        }
+    }
+
+    let (platform, on_top) = platform.expect("the level has no platforms");
+    let pepper = sim.entities::<Pepper>()[0];
+    sim.teleport(Vec3::new(on_top.x, on_top.y + 0.1, 999.0));
+    sim.touch(player, platform);
+    sim.touch(player, pepper);
+    sim.step(1);
+
+    assert_eq!(sim.player().peppers, 1);
+    assert!(sim.world().get_entity(pepper).is_none());
+}
+
+#[test]
+fn enemy_fireballs_clear_the_ground_and_reach_the_player() {
+    let level = LevelFile {
+        name: "spitter".to_string(),
+        spawn: 5,
+        goal: 59,
+        blocks: vec![BlockType::Flat; 60],
+        peppers: Vec::new(),
+        enemies: vec![EnemySpawn {
+            block: 16,
+            kind: "spitter".to_string(),
+        }],
+        checkpoints: Vec::new(),
+    };
+    let ground = level.surface(10).y;
+
+    let mut sim = Simulation::with_level(level);
+    sim.step(30);
+    let health = sim.player().health;
+
+    let mut fireball = None;
+    for _ in 0..200 {
+        sim.step(1);
+
+        fireball = sim
+            .entities::<Fireball>()
+            .into_iter()
+            .find(|entity| sim.world().get::<Fireball>(*entity).unwrap().faction == Faction::Enemy);
+
+        if fireball.is_some() {
+            break;
+        }
+    }
+
+    let fireball = fireball.expect("the spitter never fired");
+    let start = sim.world().get::<Transform>(fireball).unwrap().translation;
+    assert!(start.y - ENEMY_FIREBALL_RADIUS > ground);
+
+    // Long enough to cross the distance between them, but not for the spitter to walk into the player
+    sim.step(15);
+
+    let alive = sim.world().get_entity(fireball).is_some();
+    assert!(alive || sim.player().health < health);
+}
+
+#[test]
+fn goal_stands_at_the_end_of_the_level() {
+    let mut sim = Simulation::new(1);
+    assert_eq!(sim.count::<Goal>(), 0);
+
+    sim.teleport(Vec3::new((MAP_LEN - 10) as f32 * BLOCK_SIZE, 2.0, 999.0));
+    sim.step(3);
+
+    let goals = sim.entities::<Goal>();
+    assert_eq!(goals.len(), 1);
+
+    let goal = sim.world().get::<Transform>(goals[0]).unwrap().translation;
+    assert!((goal.x - (MAP_LEN - 1) as f32 * BLOCK_SIZE).abs() < 0.01);
+}
+
+#[test]
+fn finishing_a_level_moves_on_to_a_harder_one() {
+    let mut sim = Simulation::new(1);
+    let player = sim.entities::<Player>()[0];
+
+    sim.step(60);
+    sim.hurt(player, 10.0, DamageKind::Contact);
+    sim.step(1);
+    sim.complete_level();
+    sim.step(2);
+
+    let stats = *sim.world().resource::<RunStats>();
+    assert_eq!(sim.state(), AppState::Victory);
+    assert!(stats.time > 1.0);
+    assert_eq!(stats.damage_taken, 10.0);
+
+    sim.next_level();
+    sim.step(2);
+
+    assert_eq!(sim.state(), AppState::Playing);
+    assert_eq!(*sim.world().resource::<LevelNumber>(), LevelNumber(1));
+    assert_ne!(*sim.world().resource::<TerrainSeed>(), TerrainSeed(1));
+    assert_eq!(sim.world().resource::<RunStats>().damage_taken, 0.0);
+}
+
+#[test]
+fn finished_runs_are_saved() {
+    let mut sim = Simulation::new(3);
+
+    sim.step(30);
+    sim.complete_level();
+    sim.step(2);
+
+    let save = sim.world().resource::<SaveData>().clone();
+    let record = &save.seeds[&3];
+    assert_eq!(save.lifetime.runs, 1);
+    assert_eq!(save.lifetime.levels_completed, 1);
+    assert_eq!(record.high_scores.len(), 1);
+    assert!(record.best_time.is_some());
+
+    let storage = sim.world().resource::<SaveStorage>();
+    let text = storage.read(SaveData::NAME).unwrap().unwrap();
+    assert_eq!(SaveData::parse(&text).unwrap(), save);
+}
+
+#[test]
+fn falling_respawns_the_player_at_the_last_checkpoint() {
+    let mut sim = Simulation::new(1);
+    let config = sim.world().resource::<GameConfig>().clone();
+    let start = sim.player().health;
+
+    let spacing = config.terrain.checkpoint_spacing;
+    sim.teleport(Vec3::new(spacing as f32 * BLOCK_SIZE, 2.0, 999.0));
+    sim.step(3);
+
+    let post = sim.entities::<CheckpointPost>()[0];
+    let reached = *sim.world().get::<CheckpointPost>(post).unwrap();
+    let at_post = sim.world().get::<Transform>(post).unwrap().translation;
+    sim.teleport(Vec3::new(at_post.x, at_post.y, 999.0));
+    sim.step(3);
+
+    assert_eq!(sim.world().resource::<Checkpoint>().0, Some(reached));
+    let respawn = reached.respawn;
+
+    sim.teleport(Vec3::new(respawn.x + 1.0, -100.0, 999.0));
+    sim.step(2);
+
+    let player = sim.player();
+    assert!(player.translation.distance(respawn) < 0.1);
+    assert_eq!(player.health, start - config.player.fall_damage);
+
+    // Taken like any other hit
+    let stats = *sim.world().resource::<RunStats>();
+    assert_eq!(stats.damage_taken, config.player.fall_damage);
+}
+
+#[test]
+fn running_out_of_lives_ends_the_game() {
+    let mut sim = Simulation::new(1);
+    let player = sim.entities::<Player>()[0];
+    let lives = sim.world().resource::<Lives>().0;
+
+    for life in (0..lives).rev() {
+        sim.hurt(player, 10_000.0, DamageKind::Contact);
+        sim.step(2);
+
+        assert_eq!(sim.world().resource::<Lives>().0, life);
+
+        // Outlasts the invulnerability after respawning
+        sim.step(180);
+    }
+
+    assert_eq!(sim.state(), AppState::GameOver);
+}
+
+#[test]
+fn killing_enemies_scores_and_starts_a_combo() {
+    let mut sim = Simulation::new(1);
+    let enemy = sim.entities::<Enemy>()[0];
+
+    sim.hurt(enemy, 10_000.0, DamageKind::Fireball);
+    sim.step(2);
+
+    let stats = *sim.world().resource::<RunStats>();
+    assert_eq!(stats.enemies_killed, 1);
+    assert_eq!(stats.fireballs_hit, 1);
+    assert!(stats.damage_dealt > 0.0);
+    assert_eq!(stats.multiplier(), 2);
+    assert!(stats.score() >= 100);
+}
+
+#[test]
+fn saved_runs_carry_on_where_they_left_off() {
+    let mut sim = Simulation::new(4);
+    let player = sim.entities::<Player>()[0];
+
+    sim.teleport(Vec3::new(120.0 * BLOCK_SIZE, 2.0, 999.0));
+    sim.step(5);
+
+    let enemy = sim.entities::<Enemy>()[0];
+    sim.hurt(player, 20.0, DamageKind::Contact);
+    sim.hurt(enemy, 1.0, DamageKind::Contact);
+    sim.step(2);
+
+    let saved = sim.player();
+    let enemy_health = sim.world().get::<Health>(enemy).unwrap().0;
+    let snapshot = sim.save_snapshot();
+    assert_eq!(snapshot.seed, 4);
+    assert_eq!(snapshot.peppers.len(), sim.peppers());
+    assert_eq!(snapshot.enemies.len(), sim.enemies());
+
+    let mut resumed = Simulation::new(9);
+    resumed.load_snapshot(snapshot.clone());
+    resumed.step(5);
+
+    assert_eq!(*resumed.world().resource::<TerrainSeed>(), TerrainSeed(4));
+    assert_eq!(resumed.chunks(), sim.chunks());
+    assert_eq!(resumed.peppers(), snapshot.peppers.len());
+    assert_eq!(resumed.enemies(), snapshot.enemies.len());
+    assert_eq!(resumed.player().health, saved.health);
+    assert!(resumed.player().translation.distance(saved.translation) < 0.5);
+    assert_eq!(
+        resumed.world().resource::<RunStats>().damage_taken,
+        snapshot.stats.damage_taken
+    );
+
+    let healths: Vec<f32> = resumed
+        .entities::<Enemy>()
+        .into_iter()
+        .map(|enemy| resumed.world().get::<Health>(enemy).unwrap().0)
+        .collect();
+    assert!(healths.contains(&enemy_health));
+}
+
+#[test]
+fn saved_runs_are_deleted_once_carried_on_or_finished() {
+    let mut sim = Simulation::new(2);
+    let saved = |sim: &mut Simulation| {
+        let storage = sim.world().resource::<SaveStorage>();
+        storage.read(Snapshot::NAME).unwrap().is_some()
+    };
+
+    sim.step(5);
+    let snapshot = sim.save_snapshot();
+    assert!(saved(&mut sim));
+
+    sim.load_snapshot(snapshot);
+    sim.step(2);
+    assert!(!saved(&mut sim));
+
+    sim.save_snapshot();
+    sim.complete_level();
+    sim.step(2);
+    assert_eq!(sim.state(), AppState::Victory);
+    assert!(!saved(&mut sim));
+}
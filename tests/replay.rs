@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+use kajam_lib::{
+    headless::Simulation,
+    input::{InputFrame, Replay},
+};
+
+fn hold_right(ticks: usize) -> impl Iterator<Item = InputFrame> {
+    std::iter::repeat(InputFrame {
+        right: true,
+        ..InputFrame::idle()
+    })
+    .take(ticks)
+}
+
+#[test]
+fn scripted_input_moves_the_player() {
+    let mut sim = Simulation::new(3);
+
+    sim.queue(hold_right(60));
+    sim.step(60);
+
+    assert!(sim.player().velocity.x > 0.0);
+}
+
+#[test]
+fn replay_reproduces_the_run() {
+    let mut sim = Simulation::new(3);
+
+    sim.queue(hold_right(90));
+    sim.queue([InputFrame {
+        jump: true,
+        fire: Some(Vec2::new(2.0, 1.0)),
+        ..InputFrame::idle()
+    }]);
+    sim.step(120);
+
+    let text = ron::ser::to_string(&sim.replay()).unwrap();
+    let replay: Replay = ron::de::from_str(&text).unwrap();
+    assert_eq!(replay, sim.replay());
+
+    let mut replayed = Simulation::from_replay(&replay);
+    replayed.step(replay.frames.len() as u32 - 1);
+
+    assert_eq!(replayed.player(), sim.player());
+    assert_eq!(replayed.enemies(), sim.enemies());
+    assert_eq!(replayed.peppers(), sim.peppers());
+}
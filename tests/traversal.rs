@@ -0,0 +1,55 @@
+use kajam_lib::{
+    config::GameConfig,
+    terrain::{flattened_features, generate_blocks, TerrainSeed, MAP_LEN},
+    traversal::{validate, Reach},
+};
+
+const SEEDS: u64 = 2000;
+
+fn assert_traversable(reach: &Reach, seeds: u64) {
+    for seed in 0..seeds {
+        let blocks = generate_blocks(&TerrainSeed(seed), MAP_LEN, reach);
+        let problems = validate(&blocks, reach);
+
+        assert!(
+            problems.is_empty(),
+            "seed {}: {}",
+            seed,
+            problems
+                .iter()
+                .map(|problem| problem.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
+
+#[test]
+fn generated_levels_can_be_completed() {
+    assert_traversable(&Reach::new(&GameConfig::default()), SEEDS);
+}
+
+#[test]
+fn generated_levels_find_a_passable_feature_without_flattening() {
+    let reach = Reach::new(&GameConfig::default());
+
+    for seed in 0..SEEDS {
+        assert_eq!(
+            flattened_features(&TerrainSeed(seed), MAP_LEN, &reach),
+            Vec::<u32>::new(),
+            "seed {}",
+            seed
+        );
+    }
+}
+
+#[test]
+fn levels_are_repaired_for_a_weaker_player() {
+    let reach = Reach {
+        jump_speed: 1.2,
+        max_slope: 30.0,
+        ..Reach::new(&GameConfig::default())
+    };
+
+    assert_traversable(&reach, SEEDS / 4);
+}